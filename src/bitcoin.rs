@@ -4,28 +4,40 @@ use std::{
     future::Future,
     process::Stdio,
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
 use async_trait::async_trait;
 use bitcoin::Address;
-use bitcoincore_rpc::{json::AddressType::Bech32m, Auth, Client, RpcApi};
+use bitcoincore_rpc::{json::AddressType::Bech32m, jsonrpc, Auth, Client, RpcApi};
+use bollard::query_parameters::{
+    RemoveContainerOptions, StopContainerOptions, WaitContainerOptions,
+};
 use futures::TryStreamExt;
-use tokio::{process::Command, sync::OnceCell, time::sleep};
+use tokio::{process::Command, sync::OnceCell};
 use tracing::{debug, info, trace};
 
 use super::{
     config::BitcoinConfig,
     docker::DockerEnv,
     framework::TestContext,
-    traits::{NodeT, Restart, SpawnOutput},
+    traits::{NodeT, Restart, SpawnOutput, StopReport},
     Result,
 };
-use crate::{log_provider::LogPathProvider, node::NodeKind};
+use crate::{
+    log_provider::LogPathProvider,
+    node::{docker_client, NodeKind},
+    registry::{container_status, NodeStatus},
+    wait::Wait,
+    Error,
+};
 
 pub const DEFAULT_FINALITY_DEPTH: u64 = 5;
 
+// bitcoind `RPC_CLIENT_NODE_NOT_CONNECTED`, a peer not being connected or not found
+const RPC_CLIENT_NODE_NOT_CONNECTED: i32 = -29;
+
 pub struct BitcoinNode {
     spawn_output: SpawnOutput,
     pub config: BitcoinConfig,
@@ -66,16 +78,16 @@ impl BitcoinNode {
         target_len: usize,
         timeout: Option<Duration>,
     ) -> Result<()> {
-        let timeout = timeout.unwrap_or(Duration::from_secs(300));
-        let start = Instant::now();
-        while start.elapsed() < timeout {
-            let mempool_len = self.get_raw_mempool().await?.len();
-            if mempool_len >= target_len {
-                return Ok(());
-            }
-            sleep(Duration::from_millis(500)).await;
-        }
-        bail!("Timeout waiting for mempool to reach length {}", target_len)
+        Wait::new(format!("mempool to reach length {target_len}"))
+            .with_node(NodeKind::Bitcoin)
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(300)))
+            .with_interval(Duration::from_millis(500))
+            .until(
+                || async { Ok(self.get_raw_mempool().await?.len()) },
+                |mempool_len| *mempool_len >= target_len,
+            )
+            .await
+            .map(drop)
     }
 
     pub async fn fund_wallet(&self, name: String, blocks: u64) -> Result<()> {
@@ -103,18 +115,43 @@ impl BitcoinNode {
     }
 
     async fn wait_for_shutdown(&self) -> Result<()> {
-        let timeout_duration = Duration::from_secs(30);
-        let start = std::time::Instant::now();
+        Wait::new("daemon to stop")
+            .with_node(NodeKind::Bitcoin)
+            .with_timeout(Duration::from_secs(30))
+            .with_interval(Duration::from_millis(200))
+            .until_true(|| async { Ok(!self.is_process_running().await?) })
+            .await?;
+        info!("Bitcoin daemon has stopped successfully");
+        Ok(())
+    }
+
+    /// Stop bitcoind through RPC and wait for its process or container to exit
+    pub async fn shutdown(&mut self) -> Result<StopReport> {
+        self.expect_exit().await;
+        RpcApi::stop(self).await?;
+        NodeT::stop(self).await
+    }
 
-        while start.elapsed() < timeout_duration {
-            if !self.is_process_running().await? {
-                info!("Bitcoin daemon has stopped successfully");
-                return Ok(());
+    /// Local process status is read with `pgrep`, its exit status being unknown
+    pub async fn status(&self) -> Result<NodeStatus> {
+        match &self.spawn_output {
+            SpawnOutput::Child(_) => Ok(if self.is_process_running().await? {
+                NodeStatus::Running
+            } else {
+                NodeStatus::Exited {
+                    exit_code: None,
+                    signal: None,
+                }
+            }),
+            SpawnOutput::Container(output) => {
+                container_status(
+                    docker_client(&self.docker_env)?,
+                    &output.id,
+                    NodeKind::Bitcoin,
+                )
+                .await
             }
-            sleep(Duration::from_millis(200)).await;
         }
-
-        bail!("Timeout waiting for Bitcoin daemon to stop")
     }
 
     async fn is_process_running(&self) -> Result<bool> {
@@ -127,6 +164,15 @@ impl BitcoinNode {
         Ok(output.status.success())
     }
 
+    // Flag container exit as requested, as bitcoind shuts down on its own after a `stop` RPC call
+    async fn expect_exit(&self) {
+        if let (SpawnOutput::Container(output), Some(env)) =
+            (&self.spawn_output, self.docker_env.as_ref())
+        {
+            env.expect_exit(&output.id).await;
+        }
+    }
+
     // Infallible, discard already loaded errors
     async fn load_wallets(&self) {
         let _ = self.load_wallet(&NodeKind::Bitcoin.to_string()).await;
@@ -147,16 +193,20 @@ impl BitcoinNode {
         );
 
         let stderr_path = config.stderr_path();
-        let stderr_file = File::create(stderr_path).context("Failed to create stderr file")?;
+        let stderr_file =
+            File::create(&stderr_path).map_err(Error::node_io(NodeKind::Bitcoin, &stderr_path))?;
 
-        Command::new("bitcoind")
-            .args(&args)
+        let mut cmd = Command::new("bitcoind");
+        cmd.args(&args)
             .kill_on_drop(true)
             .envs(config.env.clone())
-            .stderr(Stdio::from(stderr_file))
-            .spawn()
-            .context("Failed to spawn bitcoind process")
-            .map(SpawnOutput::Child)
+            .stderr(Stdio::from(stderr_file));
+        Ok(SpawnOutput::Child(cmd.spawn().map_err(|e| {
+            Error::SpawnFailed {
+                node: NodeKind::Bitcoin,
+                source: e.into(),
+            }
+        })?))
     }
 
     pub async fn generate(
@@ -216,7 +266,15 @@ impl NodeT for BitcoinNode {
 
     async fn spawn(config: &Self::Config, docker: &Arc<Option<DockerEnv>>) -> Result<SpawnOutput> {
         match docker.as_ref() {
-            Some(docker) if docker.bitcoin() => docker.spawn(config.into()).await,
+            Some(docker) if docker.dockerized(NodeKind::Bitcoin) => {
+                docker.spawn(config.into()).await.map_err(|source| {
+                    Error::SpawnFailed {
+                        node: NodeKind::Bitcoin,
+                        source,
+                    }
+                    .into()
+                })
+            }
             _ => Self::spawn(config),
         }
     }
@@ -227,25 +285,18 @@ impl NodeT for BitcoinNode {
 
     async fn wait_for_ready(&self, timeout: Option<Duration>) -> Result<()> {
         trace!("Waiting for ready");
-        let start = Instant::now();
-        let timeout = timeout.unwrap_or(Duration::from_secs(30));
-        while start.elapsed() < timeout {
-            if wait_for_rpc_ready(&self.client, Some(timeout))
-                .await
-                .is_ok()
-            {
-                return Ok(());
-            }
-            tokio::time::sleep(Duration::from_millis(500)).await;
-        }
-        bail!("Node failed to become ready within the specified timeout")
+        wait_for_rpc_ready(
+            &self.client,
+            Some(timeout.unwrap_or(Duration::from_secs(30))),
+        )
+        .await
     }
 
     fn client(&self) -> &Self::Client {
         &self.client
     }
 
-    fn env(&self) -> Vec<(&'static str, &'static str)> {
+    fn env(&self) -> Vec<(String, String)> {
         self.config.env.clone()
     }
 
@@ -261,6 +312,7 @@ impl NodeT for BitcoinNode {
 #[async_trait]
 impl Restart for BitcoinNode {
     async fn wait_until_stopped(&mut self) -> Result<()> {
+        self.expect_exit().await;
         self.client.stop().await?;
         self.stop().await?;
 
@@ -270,13 +322,17 @@ impl Restart for BitcoinNode {
                 let Some(env) = self.docker_env.as_ref() else {
                     bail!("Missing docker environment")
                 };
-                env.docker.stop_container(&output.id, None).await?;
+                env.docker
+                    .stop_container(&output.id, None::<StopContainerOptions>)
+                    .await?;
 
                 env.docker
-                    .wait_container::<String>(&output.id, None)
+                    .wait_container(&output.id, None::<WaitContainerOptions>)
                     .try_collect::<Vec<_>>()
                     .await?;
-                env.docker.remove_container(&output.id, None).await?;
+                env.docker
+                    .remove_container(&output.id, None::<RemoveContainerOptions>)
+                    .await?;
                 info!("Docker container {} succesfully removed", output.id);
                 Ok(())
             }
@@ -322,29 +378,27 @@ impl BitcoinNodeCluster {
 
     pub async fn stop_all(&mut self) -> Result<()> {
         for node in &mut self.inner {
-            RpcApi::stop(node).await?;
-            node.stop().await?;
+            node.shutdown().await?;
         }
         Ok(())
     }
 
     pub async fn wait_for_sync(&self, timeout: Option<Duration>) -> Result<()> {
-        let start = Instant::now();
-        let timeout = timeout.unwrap_or(Duration::from_secs(60));
-        while start.elapsed() < timeout {
-            let mut heights = HashSet::new();
-            for node in &self.inner {
-                let height = node.get_block_count().await?;
-                heights.insert(height);
-            }
-
-            if heights.len() == 1 {
-                return Ok(());
-            }
-
-            sleep(Duration::from_secs(1)).await;
-        }
-        bail!("Nodes failed to sync within the specified timeout")
+        Wait::new("nodes to sync")
+            .with_node(NodeKind::Bitcoin)
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(60)))
+            .until(
+                || async {
+                    let mut heights = HashSet::new();
+                    for node in &self.inner {
+                        heights.insert(node.get_block_count().await?);
+                    }
+                    Ok(heights)
+                },
+                |heights| heights.len() == 1,
+            )
+            .await
+            .map(drop)
     }
 
     // Connect all bitcoin nodes between them
@@ -429,11 +483,9 @@ impl BitcoinNodeCluster {
                     for peer_id in peer_ids {
                         match from_node.disconnect_node_by_id(peer_id as u32).await {
                             Ok(_) => (),
-                            Err(e) => {
-                                if !e.to_string().contains("Node not found") {
-                                    bail!("{e}")
-                                }
+                            Err(e) if rpc_error_code(&e) == Some(RPC_CLIENT_NODE_NOT_CONNECTED) => {
                             }
+                            Err(e) => bail!("{e}"),
                         }
                     }
 
@@ -470,47 +522,55 @@ impl BitcoinNodeCluster {
     pub fn iter(&self) -> std::slice::Iter<'_, BitcoinNode> {
         self.inner.iter()
     }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, BitcoinNode> {
+        self.inner.iter_mut()
+    }
 }
 
 async fn wait_for_rpc_ready(client: &Client, timeout: Option<Duration>) -> Result<()> {
-    let start = Instant::now();
-    let timeout = timeout.unwrap_or(Duration::from_secs(15));
-    while start.elapsed() < timeout {
-        match client.get_blockchain_info().await {
-            Ok(_) => return Ok(()),
-            Err(e) => {
-                trace!("[wait_for_rpc_ready] error {e}");
-                sleep(Duration::from_millis(500)).await;
-            }
-        }
-    }
-    bail!("Timeout waiting for RPC to be ready")
+    Wait::new("RPC to be ready")
+        .with_node(NodeKind::Bitcoin)
+        .with_timeout(timeout.unwrap_or(Duration::from_secs(15)))
+        .with_interval(Duration::from_millis(500))
+        .retry_all_errors()
+        .until(
+            || async { Ok(client.get_blockchain_info().await.map(drop)?) },
+            |_| true,
+        )
+        .await
 }
 
-pub async fn wait_until<F, Fut>(mut f: F) -> Result<()>
+/// Poll `f` until it returns true for up to 60 seconds, see `wait_until_with`
+pub async fn wait_until<F, Fut>(f: F) -> Result<()>
 where
     F: FnMut() -> Fut,
     Fut: Future<Output = Result<bool>>,
 {
-    let timeout = Duration::from_secs(60);
-    let start = Instant::now();
-
-    while start.elapsed() < timeout {
-        match f().await {
-            Ok(true) => return Ok(()),
-            Ok(false) => {
-                sleep(Duration::from_secs(1)).await;
-                continue;
-            }
-            Err(e) => {
-                if e.to_string().contains("node not connected") {
-                    sleep(Duration::from_secs(1)).await;
-                    continue;
-                }
-                return Err(e);
-            }
-        }
-    }
+    let wait = Wait::new("wait_until condition")
+        .with_node(NodeKind::Bitcoin)
+        .with_timeout(Duration::from_secs(60))
+        .with_retryable(|e| {
+            e.downcast_ref::<bitcoincore_rpc::Error>()
+                .and_then(rpc_error_code)
+                == Some(RPC_CLIENT_NODE_NOT_CONNECTED)
+        });
+    wait_until_with(wait, f).await
+}
 
-    bail!("wait_until timed out after {} seconds", timeout.as_secs())
+/// Poll `f` until it returns true, with the timeout and retried errors of `wait`
+pub async fn wait_until_with<F, Fut>(wait: Wait, f: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    wait.until_true(f).await
+}
+
+/// JSON-RPC error code of a bitcoin RPC call error response
+pub fn rpc_error_code(e: &bitcoincore_rpc::Error) -> Option<i32> {
+    match e {
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::error::Error::Rpc(error)) => Some(error.code),
+        _ => None,
+    }
 }
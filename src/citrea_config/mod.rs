@@ -1,12 +1,13 @@
 // Config imported as is from `citrea` repository `node-config` directory.
 // This is done in order not to have cyclical dependencies with `citrea`.
-// Should ideally be automatically kept in sync somehow but manually copied here for the time being.
+// Manually copied here, `schema` checks them against vendored upstream samples.
 // Configs are stable and not expected to change much.
 
 pub(crate) mod batch_prover;
 pub(crate) mod bitcoin;
 pub(crate) mod light_client_prover;
 pub(crate) mod rollup;
+pub(crate) mod schema;
 pub(crate) mod sequencer;
 
 #[cfg(test)]
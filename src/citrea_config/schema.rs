@@ -0,0 +1,279 @@
+// Sync checks between the configs of this module and citrea `node-config`.
+// Upstream sample configs are vendored per citrea version under `resources/citrea_config/<version>`,
+// `latest` matching the structs of this module.
+// Release samples are fetched with `make vendor-citrea-config CITREA_TAG=<tag>` and committed as is,
+// never edited to match local structs, so that `check` reports actual upstream drift.
+// When bumping citrea, vendor the new release and update `latest` and the structs until it checks clean.
+
+use std::{fmt, path::PathBuf};
+
+use anyhow::{bail, Context};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    batch_prover::BatchProverConfig, light_client_prover::LightClientProverConfig,
+    rollup::RollupConfig, sequencer::SequencerConfig,
+};
+use crate::{node::NodeKind, utils::get_workspace_root, Result};
+
+pub const LATEST_CONFIG_VERSION: &str = "latest";
+
+const ROLLUP_SAMPLE: &str = "rollup";
+
+/// Difference between a local config struct and an upstream sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaMismatch {
+    /// Upstream field unknown locally, i.e. missing or renamed in our struct
+    Unknown { config: String, path: String },
+    /// Upstream sample doesn't deserialize, i.e. local field missing or renamed upstream, or type change
+    Invalid {
+        config: String,
+        path: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown { config, path } => write!(f, "{config}.{path}: unknown field"),
+            Self::Invalid {
+                config,
+                path,
+                error,
+            } => write!(f, "{config}.{path}: {error}"),
+        }
+    }
+}
+
+/// Vendored upstream config samples of a citrea version.
+#[derive(Debug, Clone)]
+pub struct ConfigSchema {
+    version: String,
+    dir: PathBuf,
+}
+
+impl ConfigSchema {
+    pub fn new(version: impl Into<String>) -> Result<Self> {
+        let version = version.into();
+        let dir = schemas_dir().join(&version);
+        if !dir.is_dir() {
+            bail!(
+                "Unknown citrea config version {version}, expected one of {:?}",
+                Self::versions()?
+            );
+        }
+        Ok(Self { version, dir })
+    }
+
+    pub fn latest() -> Result<Self> {
+        Self::new(LATEST_CONFIG_VERSION)
+    }
+
+    /// Versions with vendored samples
+    pub fn versions() -> Result<Vec<String>> {
+        let mut versions = std::fs::read_dir(schemas_dir())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        versions.sort();
+        Ok(versions)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Check every local config against this version samples
+    pub fn check(&self) -> Result<Vec<SchemaMismatch>> {
+        Ok([
+            check_sample::<SequencerConfig>("sequencer", &self.sample("sequencer")?),
+            check_sample::<BatchProverConfig>("batch_prover", &self.sample("batch_prover")?),
+            check_sample::<LightClientProverConfig>(
+                "light_client_prover",
+                &self.sample("light_client_prover")?,
+            ),
+            // Includes `BitcoinServiceConfig` as `da`
+            check_sample::<RollupConfig>(ROLLUP_SAMPLE, &self.sample(ROLLUP_SAMPLE)?),
+        ]
+        .concat())
+    }
+
+    /// Serialize `config` of node `kind` for this version.
+    /// Fields dropped since this version are removed and fields unknown locally are taken from the sample.
+    /// Optional fields absent from both samples are left untouched.
+    pub(crate) fn adapt_node_config<C: Serialize>(
+        &self,
+        kind: NodeKind,
+        config: &C,
+    ) -> Result<toml::Table> {
+        self.adapt(node_sample_name(kind)?, config)
+    }
+
+    pub(crate) fn adapt_rollup_config(&self, config: &RollupConfig) -> Result<toml::Table> {
+        self.adapt(ROLLUP_SAMPLE, config)
+    }
+
+    fn adapt<C: Serialize>(&self, name: &str, config: &C) -> Result<toml::Table> {
+        let mut table = toml::Table::try_from(config)?;
+        if self.version != LATEST_CONFIG_VERSION {
+            let latest = Self::latest()?.sample_table(name)?;
+            adapt_table(&mut table, &latest, &self.sample_table(name)?);
+        }
+        Ok(table)
+    }
+
+    fn sample(&self, name: &str) -> Result<String> {
+        let path = self.dir.join(format!("{name}.toml"));
+        std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    fn sample_table(&self, name: &str) -> Result<toml::Table> {
+        Ok(toml::from_str(&self.sample(name)?)?)
+    }
+}
+
+fn schemas_dir() -> PathBuf {
+    get_workspace_root().join("resources").join("citrea_config")
+}
+
+fn node_sample_name(kind: NodeKind) -> Result<&'static str> {
+    Ok(match kind {
+        NodeKind::Sequencer => "sequencer",
+        NodeKind::BatchProver => "batch_prover",
+        NodeKind::LightClientProver => "light_client_prover",
+        NodeKind::Bitcoin | NodeKind::FullNode => bail!("No citrea node config for {kind}"),
+    })
+}
+
+/// Deserialize upstream `sample` as `T`, reporting unknown fields and deserialization error paths.
+pub fn check_sample<T: DeserializeOwned>(config: &str, sample: &str) -> Vec<SchemaMismatch> {
+    let mut mismatches = Vec::new();
+    let mut unknown = Vec::new();
+
+    let deserializer = toml::Deserializer::new(sample);
+    let mut track = |path: serde_ignored::Path| unknown.push(path.to_string());
+    let deserializer = serde_ignored::Deserializer::new(deserializer, &mut track);
+    if let Err(e) = serde_path_to_error::deserialize::<_, T>(deserializer) {
+        mismatches.push(SchemaMismatch::Invalid {
+            config: config.to_string(),
+            path: e.path().to_string(),
+            error: e.into_inner().message().trim().to_string(),
+        });
+    }
+
+    mismatches.extend(unknown.into_iter().map(|path| SchemaMismatch::Unknown {
+        config: config.to_string(),
+        path,
+    }));
+    mismatches
+}
+
+fn adapt_table(table: &mut toml::Table, latest: &toml::Table, version: &toml::Table) {
+    table.retain(|key, _| !latest.contains_key(key) || version.contains_key(key));
+
+    for (key, version_value) in version {
+        match (table.get_mut(key), latest.get(key)) {
+            (Some(toml::Value::Table(table)), Some(toml::Value::Table(latest))) => {
+                if let toml::Value::Table(version) = version_value {
+                    adapt_table(table, latest, version);
+                }
+            }
+            (None, None) => {
+                table.insert(key.clone(), version_value.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_schema() {
+        let mismatches = ConfigSchema::latest().unwrap().check().unwrap();
+        assert!(
+            mismatches.is_empty(),
+            "citrea config out of sync:\n{}",
+            mismatches
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_check_sample() {
+        let mismatches = check_sample::<LightClientProverConfig>(
+            "light_client_prover",
+            r#"
+            proving_mode = "skip"
+            proof_sampling_number = 500
+            enable_recovery = true
+            l1_start_height = 15
+            "#,
+        );
+        assert_eq!(mismatches.len(), 2);
+        assert!(matches!(
+            &mismatches[0],
+            SchemaMismatch::Invalid { error, .. } if error.contains("initial_da_height")
+        ));
+        assert_eq!(
+            mismatches[1],
+            SchemaMismatch::Unknown {
+                config: "light_client_prover".to_string(),
+                path: "l1_start_height".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_adapt_table() {
+        let latest: toml::Table = toml::from_str(
+            r#"
+            kept = 1
+            dropped = 2
+            [nested]
+            dropped = 3
+            "#,
+        )
+        .unwrap();
+        let version: toml::Table = toml::from_str(
+            r#"
+            kept = 1
+            added = 4
+            [nested]
+            added = 5
+            "#,
+        )
+        .unwrap();
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            kept = 10
+            dropped = 20
+            optional = 30
+            [nested]
+            dropped = 40
+            "#,
+        )
+        .unwrap();
+
+        adapt_table(&mut table, &latest, &version);
+
+        let expected: toml::Table = toml::from_str(
+            r#"
+            kept = 10
+            optional = 30
+            added = 4
+            [nested]
+            added = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+}
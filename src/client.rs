@@ -1,18 +1,22 @@
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
-use alloy_primitives::U64;
-use anyhow::{bail, Result};
+use alloy_primitives::{Address, U256, U64};
+use anyhow::Result;
 use jsonrpsee::{
-    core::client::ClientT,
+    core::{client::ClientT, traits::ToRpcParams, ClientError},
     http_client::{HttpClient, HttpClientBuilder},
     rpc_params,
 };
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 use tokio::time::sleep;
-use tracing::trace;
+
+use crate::{config::ForkSchedule, node::NodeKind, wait::Wait, Error};
 
 #[derive(Clone, Debug)]
 pub struct Client {
     client: HttpClient,
+    node: Option<NodeKind>,
 }
 
 impl Client {
@@ -21,60 +25,91 @@ impl Client {
         let client = HttpClientBuilder::default()
             .request_timeout(Duration::from_secs(120))
             .build(host)?;
-        Ok(Self { client })
+        Ok(Self { client, node: None })
+    }
+
+    /// Node the client talks to, reported in RPC and wait errors
+    pub fn with_node(mut self, node: NodeKind) -> Self {
+        self.node = Some(node);
+        self
     }
 
     pub fn http_client(&self) -> &HttpClient {
         &self.client
     }
+
+    // Call `method`, failing with `Error::Rpc`
+    async fn request<R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: impl ToRpcParams + Send,
+    ) -> Result<R> {
+        self.client
+            .request(method, params)
+            .await
+            .map_err(|e| rpc_error(self.node, method, e).into())
+    }
+}
+
+fn rpc_error(node: Option<NodeKind>, method: &str, e: ClientError) -> Error {
+    let (code, message) = match e {
+        ClientError::Call(error) => (Some(error.code()), error.message().to_string()),
+        e => (None, e.to_string()),
+    };
+    Error::Rpc {
+        node,
+        method: method.to_string(),
+        code,
+        message,
+    }
 }
 
 impl Client {
     // TODO Use SequencerRpcClient trait
     pub async fn send_publish_batch_request(&self) -> Result<()> {
-        let r = self
-            .client
-            .request("citrea_testPublishBlock", rpc_params![])
-            .await
-            .map_err(Into::into);
+        let r = self.request("citrea_testPublishBlock", rpc_params![]).await;
         sleep(Duration::from_millis(100)).await;
         r
     }
 
     pub async fn ledger_get_last_scanned_l1_height(&self) -> Result<u64> {
-        Ok(self
-            .client
-            .request("ledger_getLastScannedL1Height", rpc_params![])
+        self.request("ledger_getLastScannedL1Height", rpc_params![])
             .await
-            .map(|v: U64| v.try_into().expect("U64 to u64 must succeed"))?)
+            .map(|v: U64| v.try_into().expect("U64 to u64 must succeed"))
     }
 
     pub async fn ledger_get_head_l2_block_height(&self) -> Result<u64> {
-        Ok(self
-            .client
-            .request("ledger_getHeadL2BlockHeight", rpc_params![])
+        self.request("ledger_getHeadL2BlockHeight", rpc_params![])
             .await
-            .map(|v: U64| v.try_into().expect("U64 to u64 must succeed"))?)
+            .map(|v: U64| v.try_into().expect("U64 to u64 must succeed"))
     }
 
-    pub async fn wait_for_l2_block(&self, num: u64, timeout: Option<Duration>) -> Result<()> {
-        let start = SystemTime::now();
-        let timeout = timeout.unwrap_or(Duration::from_secs(30)); // Default 30 seconds timeout
-        loop {
-            trace!("Waiting for l2 block {}", num);
-            let latest_block = self.ledger_get_head_l2_block_height().await?;
+    pub async fn ledger_get_l2_block_by_number(&self, num: u64) -> Result<Option<Value>> {
+        self.request("ledger_getL2BlockByNumber", rpc_params![U64::from(num)])
+            .await
+    }
 
-            if latest_block >= num {
-                break;
-            }
+    /// Fork activation heights of the running binary
+    pub async fn citrea_get_forks(&self) -> Result<ForkSchedule> {
+        self.request("citrea_getForks", rpc_params![]).await
+    }
 
-            let now = SystemTime::now();
-            if start + timeout <= now {
-                bail!("Timeout. Latest L2 block is {:?}", latest_block);
-            }
+    pub async fn eth_get_balance(&self, address: Address, block: u64) -> Result<U256> {
+        self.request("eth_getBalance", rpc_params![address, U64::from(block)])
+            .await
+    }
 
-            sleep(Duration::from_secs(1)).await;
+    pub async fn wait_for_l2_block(&self, num: u64, timeout: Option<Duration>) -> Result<()> {
+        let mut wait = Wait::new(format!("L2 block {num}"))
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(30)));
+        if let Some(node) = self.node {
+            wait = wait.with_node(node);
         }
-        Ok(())
+        wait.until(
+            || self.ledger_get_head_l2_block_height(),
+            |latest_block| *latest_block >= num,
+        )
+        .await
+        .map(drop)
     }
 }
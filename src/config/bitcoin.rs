@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use bitcoin::Network;
 use tempfile::TempDir;
 
+use super::test_case::merge_env;
 use crate::{log_provider::LogPathProvider, node::NodeKind};
 
 #[derive(Debug, Clone)]
@@ -12,12 +13,14 @@ pub struct BitcoinConfig {
     pub rpc_user: String,
     pub rpc_password: String,
     pub data_dir: PathBuf,
-    pub extra_args: Vec<&'static str>,
+    pub extra_args: Vec<String>,
     pub network: Network,
     pub docker_image: Option<String>,
-    pub env: Vec<(&'static str, &'static str)>,
+    pub env: Vec<(String, String)>,
     pub idx: usize,
     pub docker_host: Option<String>,
+    /// Docker network subnet allowed to reach the RPC of a dockerized node
+    pub docker_subnet: Option<String>,
 }
 
 impl Default for BitcoinConfig {
@@ -36,11 +39,24 @@ impl Default for BitcoinConfig {
             env: Vec::new(),
             idx: 0,
             docker_host: None,
+            docker_subnet: None,
         }
     }
 }
 
 impl BitcoinConfig {
+    /// Set env `key`, overriding the test env
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env = merge_env([self.env, vec![(key.into(), value.into())]]);
+        self
+    }
+
+    /// Append bitcoind args, after the base ones
+    pub fn with_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.extra_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
     fn base_args(&self) -> Vec<String> {
         vec![
             "-regtest".to_string(),
@@ -60,11 +76,7 @@ impl BitcoinConfig {
     }
 
     pub fn args(&self) -> Vec<String> {
-        [
-            self.base_args(),
-            self.extra_args.iter().map(|&s| s.to_string()).collect(),
-        ]
-        .concat()
+        [self.base_args(), self.extra_args.clone()].concat()
     }
 
     /// Args to use whe running local bitcoind node
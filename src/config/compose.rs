@@ -0,0 +1,272 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use super::{
+    BitcoinConfig, CitreaImageSource, DockerConfig, FullL2NodeConfig, ResolvedImage, TestConfig,
+    CITREA_CONTAINER_BINARY_PATH,
+};
+use crate::{docker::DOCKER_HOST_GATEWAY, node::NodeKind, Result};
+
+/// docker-compose representation of a test environment.
+///
+/// Services are built from the same `DockerConfig` used when spawning containers,
+/// i.e. same images, args, ports and mounts (node dirs with generated configs and genesis dir).
+/// When docker is used, only the dockerized nodes are exported, the other ones being expected
+/// to run locally as placed by the test, and services reach the host through `host.docker.internal`.
+/// Without docker, every node is exported and services share the host network
+/// so that generated `127.0.0.1` urls keep working.
+#[derive(Debug, Serialize)]
+pub struct DockerCompose {
+    pub name: String,
+    pub services: BTreeMap<String, ComposeService>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub volumes: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub networks: BTreeMap<String, ComposeNetworkConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComposeService {
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    pub command: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub networks: Option<BTreeMap<String, ComposeNetwork>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra_hosts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComposeNetwork {
+    pub aliases: Vec<String>,
+}
+
+/// Top level network definition, pinning the subnet bitcoind RPC access is restricted to
+#[derive(Debug, Serialize)]
+pub struct ComposeNetworkConfig {
+    pub ipam: ComposeIpam,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComposeIpam {
+    pub config: Vec<BTreeMap<String, String>>,
+}
+
+impl DockerCompose {
+    pub fn from_test_config(config: &TestConfig) -> Result<Self> {
+        let test_case = &config.test_case;
+        let docker = &test_case.docker;
+        // Without docker, nodes run on the host network
+        let bridged = docker.enabled();
+        let exported = |kind: NodeKind| !bridged || docker.is_dockerized(kind);
+        let image_source = &test_case.docker.citrea_image;
+        // Sequencer hostname as targeted by the other dockerized L2 nodes
+        let sequencer_aliases = bridged.then(|| {
+            let mut aliases: Vec<String> = [
+                &config.batch_prover.rollup,
+                &config.light_client_prover.rollup,
+                &config.full_node.rollup,
+            ]
+            .into_iter()
+            .filter_map(|rollup| rollup.runner.as_ref())
+            .filter_map(|runner| url_host(&runner.sequencer_client_url))
+            .filter(|host| is_docker_hostname(host))
+            .collect();
+            aliases.sort();
+            aliases.dedup();
+            aliases
+        });
+        let aliases = bridged.then(Vec::new);
+
+        let mut compose = Self {
+            name: format!("citrea-e2e-{}", test_case.test_id.to_lowercase()),
+            services: BTreeMap::new(),
+            volumes: BTreeMap::new(),
+            networks: BTreeMap::new(),
+        };
+
+        if let (true, Some(subnet)) = (bridged, &config.bitcoin[0].docker_subnet) {
+            compose.networks.insert(
+                "default".to_string(),
+                ComposeNetworkConfig {
+                    ipam: ComposeIpam {
+                        config: vec![BTreeMap::from([("subnet".to_string(), subnet.clone())])],
+                    },
+                },
+            );
+        }
+
+        if exported(NodeKind::Bitcoin) {
+            for bitcoin in &config.bitcoin {
+                compose.add_bitcoin(bitcoin, bridged)?;
+            }
+        }
+
+        if test_case.with_sequencer && exported(NodeKind::Sequencer) {
+            compose.add_l2_node(&config.sequencer, image_source, sequencer_aliases)?;
+        }
+        if test_case.with_batch_prover && exported(NodeKind::BatchProver) {
+            compose.add_l2_node(&config.batch_prover, image_source, aliases.clone())?;
+        }
+        if test_case.with_light_client_prover && exported(NodeKind::LightClientProver) {
+            compose.add_l2_node(&config.light_client_prover, image_source, aliases.clone())?;
+        }
+        if test_case.with_full_node && exported(NodeKind::FullNode) {
+            compose.add_l2_node(&config.full_node, image_source, aliases.clone())?;
+        }
+
+        // Dependencies running locally are not part of the compose file
+        let services: Vec<String> = compose.services.keys().cloned().collect();
+        for service in compose.services.values_mut() {
+            service
+                .depends_on
+                .retain(|dependency| services.contains(dependency));
+        }
+
+        Ok(compose)
+    }
+
+    /// Write compose file to `path`
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let yaml = serde_yml::to_string(self).context("Failed to serialize compose file")?;
+        std::fs::write(path, yaml)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn add_bitcoin(&mut self, config: &BitcoinConfig, bridged: bool) -> Result<()> {
+        let name = format!("{}-{}", NodeKind::Bitcoin, config.idx);
+        // Only the first node is targeted by citrea nodes
+        let aliases = config.docker_host.iter().cloned().collect();
+        let service = self.service(
+            config.into(),
+            &CitreaImageSource::Registry,
+            Vec::new(),
+            bridged.then_some(aliases),
+        )?;
+        self.services.insert(name, service);
+        Ok(())
+    }
+
+    fn add_l2_node<T>(
+        &mut self,
+        config: &FullL2NodeConfig<T>,
+        image_source: &CitreaImageSource,
+        aliases: Option<Vec<String>>,
+    ) -> Result<()>
+    where
+        T: Clone + Debug + Serialize + Send + Sync,
+        DockerConfig: From<FullL2NodeConfig<T>>,
+    {
+        let kind = config.kind();
+        let depends_on = match kind {
+            NodeKind::Sequencer => vec![format!("{}-0", NodeKind::Bitcoin)],
+            _ => vec![
+                format!("{}-0", NodeKind::Bitcoin),
+                NodeKind::Sequencer.to_string(),
+            ],
+        };
+        let service = self.service(config.clone().into(), image_source, depends_on, aliases)?;
+        self.services.insert(kind.to_string(), service);
+        Ok(())
+    }
+
+    // `aliases` are the network aliases of the service, `None` runs it on the host network
+    fn service(
+        &mut self,
+        config: DockerConfig,
+        image_source: &CitreaImageSource,
+        depends_on: Vec<String>,
+        aliases: Option<Vec<String>>,
+    ) -> Result<ComposeService> {
+        let ResolvedImage {
+            image,
+            entrypoint,
+            binary,
+        } = image_source.resolve(config.image, config.binary)?;
+
+        self.volumes
+            .insert(config.volume.name.clone(), BTreeMap::new());
+        let volumes = [format!("{}:{}", config.volume.name, config.volume.target)]
+            .into_iter()
+            .chain(
+                config
+                    .host_dir
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|dir| format!("{dir}:{dir}")),
+            )
+            .chain(binary.map(|binary: PathBuf| {
+                format!("{}:{CITREA_CONTAINER_BINARY_PATH}:ro", binary.display())
+            }))
+            .collect();
+
+        let (ports, network_mode, networks, extra_hosts) = match aliases {
+            Some(aliases) => (
+                config
+                    .ports
+                    .iter()
+                    .map(|port| format!("127.0.0.1:{port}:{port}"))
+                    .collect(),
+                None,
+                Some(BTreeMap::from([(
+                    "default".to_string(),
+                    ComposeNetwork { aliases },
+                )])),
+                // Reach the nodes running locally
+                vec![format!("{DOCKER_HOST_GATEWAY}:host-gateway")],
+            ),
+            None => (Vec::new(), Some("host".to_string()), None, Vec::new()),
+        };
+
+        Ok(ComposeService {
+            image,
+            entrypoint,
+            command: config.cmd,
+            ports,
+            volumes,
+            environment: config.env.into_iter().collect(),
+            depends_on,
+            network_mode,
+            networks,
+            extra_hosts,
+        })
+    }
+}
+
+// Extract host from an `http://host:port` url
+fn url_host(url: &str) -> Option<String> {
+    let without_scheme = url.split("://").last()?;
+    let host = without_scheme.split([':', '/']).next()?;
+    Some(host.to_string())
+}
+
+// Whether `host` is the docker hostname of a container, as opposed to an ip or the host gateway
+fn is_docker_hostname(host: &str) -> bool {
+    host != DOCKER_HOST_GATEWAY && host != "localhost" && host.parse::<IpAddr>().is_err()
+}
+
+impl TestConfig {
+    /// Default location of the exported compose file
+    pub fn docker_compose_path(&self) -> PathBuf {
+        self.test_case.dir.join("docker-compose.yml")
+    }
+}
@@ -0,0 +1,441 @@
+//! Declarative test case definitions.
+//!
+//! A definition describes the whole topology of a test case in a single TOML or YAML file.
+//! Every section is optional and only overrides the framework defaults, i.e.
+//!
+//! ```toml
+//! scan_l1_start_height = 1
+//!
+//! [test]
+//! with_batch_prover = true
+//! timeout_secs = 120
+//!
+//! [docker]
+//! citrea = true
+//!
+//! [env]
+//! sequencer = { RUST_LOG = "debug" }
+//!
+//! [sequencer]
+//! max_l2_blocks_per_commitment = 10
+//!
+//! [rollup.full_node.rpc]
+//! max_connections = 10
+//! ```
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use anyhow::{bail, Context};
+use bitcoin::secp256k1::SecretKey;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    BatchProverConfig, BitcoinConfig, CitreaMode, LightClientProverConfig, PruningConfig,
+    RollupConfig, SequencerConfig, TestCaseConfig, TestCaseDockerConfig, TestCaseEnv, TestCaseKeys,
+};
+use crate::{node::NodeKind, Result};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestCaseDefinition {
+    pub test: TestCaseDefinitionConfig,
+    pub docker: DockerDefinitionConfig,
+    pub env: EnvDefinitionConfig,
+    pub bitcoin: BitcoinDefinitionConfig,
+    pub keys: KeysDefinitionConfig,
+    pub pruning: PruningDefinitionConfig,
+    pub scan_l1_start_height: Option<u64>,
+    /// Partial `SequencerConfig`
+    pub sequencer: toml::Table,
+    /// Partial `BatchProverConfig`
+    pub batch_prover: toml::Table,
+    /// Partial `LightClientProverConfig`
+    pub light_client_prover: toml::Table,
+    pub rollup: RollupDefinitionConfig,
+}
+
+/// Overrides of `TestCaseConfig`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestCaseDefinitionConfig {
+    pub n_nodes: Option<usize>,
+    pub with_sequencer: Option<bool>,
+    pub with_full_node: Option<bool>,
+    pub with_batch_prover: Option<bool>,
+    pub with_light_client_prover: Option<bool>,
+    pub with_citrea_cli: Option<bool>,
+    pub timeout_secs: Option<u64>,
+    pub genesis_dir: Option<String>,
+    pub mode: Option<CitreaMode>,
+    pub citrea_config_version: Option<String>,
+}
+
+/// Overrides of `TestCaseDockerConfig`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DockerDefinitionConfig {
+    pub bitcoin: Option<bool>,
+    pub citrea: Option<bool>,
+    pub sequencer: Option<bool>,
+    pub batch_prover: Option<bool>,
+    pub light_client_prover: Option<bool>,
+    pub full_node: Option<bool>,
+    pub log_timestamps: Option<bool>,
+}
+
+/// Env variables per node, see `TestCaseEnv`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvDefinitionConfig {
+    pub test: BTreeMap<String, String>,
+    pub full_node: BTreeMap<String, String>,
+    pub sequencer: BTreeMap<String, String>,
+    pub batch_prover: BTreeMap<String, String>,
+    pub light_client_prover: BTreeMap<String, String>,
+    pub bitcoin: BTreeMap<String, String>,
+}
+
+/// Overrides of `TestCaseKeys`, hex encoded private keys
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysDefinitionConfig {
+    /// Generate fresh keys, overridden by the provided ones
+    pub fresh: Option<bool>,
+    pub sequencer: Option<String>,
+    pub sequencer_da: Option<String>,
+    pub prover_da: Option<String>,
+}
+
+/// Pruning distance per L2 node, see `TestCase::pruning_config`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PruningDefinitionConfig {
+    pub batch_prover: Option<u64>,
+    pub light_client_prover: Option<u64>,
+    pub full_node: Option<u64>,
+}
+
+/// Overrides of `BitcoinConfig`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BitcoinDefinitionConfig {
+    pub extra_args: Option<Vec<String>>,
+    pub docker_image: Option<String>,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+}
+
+/// Partial `RollupConfig` per node, applied on top of the generated rollup configs
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RollupDefinitionConfig {
+    pub sequencer: toml::Table,
+    pub batch_prover: toml::Table,
+    pub light_client_prover: toml::Table,
+    pub full_node: toml::Table,
+}
+
+impl TestCaseDefinition {
+    /// Load definition from a `.toml`, `.yaml` or `.yml` file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("yaml" | "yml") => Self::from_yaml(&content),
+            _ => bail!(
+                "Unsupported test case definition format {}, expected toml or yaml",
+                path.display()
+            ),
+        }
+        .with_context(|| format!("Invalid test case definition {}", path.display()))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        Ok(serde_yml::from_str(content)?)
+    }
+
+    pub fn test_config(&self) -> Result<TestCaseConfig> {
+        let test = &self.test;
+        let default = TestCaseConfig::default();
+        Ok(TestCaseConfig {
+            n_nodes: test.n_nodes.unwrap_or(default.n_nodes),
+            with_sequencer: test.with_sequencer.unwrap_or(default.with_sequencer),
+            with_full_node: test.with_full_node.unwrap_or(default.with_full_node),
+            with_batch_prover: test.with_batch_prover.unwrap_or(default.with_batch_prover),
+            with_light_client_prover: test
+                .with_light_client_prover
+                .unwrap_or(default.with_light_client_prover),
+            with_citrea_cli: test.with_citrea_cli.unwrap_or(default.with_citrea_cli),
+            timeout: test
+                .timeout_secs
+                .map_or(default.timeout, Duration::from_secs),
+            genesis_dir: test.genesis_dir.clone().or(default.genesis_dir),
+            mode: test.mode.clone().unwrap_or(default.mode),
+            citrea_config_version: test
+                .citrea_config_version
+                .clone()
+                .or(default.citrea_config_version),
+            docker: self.docker.docker_config(default.docker),
+            keys: self.keys.keys(default.keys)?,
+            ..default
+        })
+    }
+
+    pub fn test_env(&self) -> TestCaseEnv {
+        let env = &self.env;
+        TestCaseEnv {
+            test: env_vars(&env.test),
+            full_node: env_vars(&env.full_node),
+            sequencer: env_vars(&env.sequencer),
+            batch_prover: env_vars(&env.batch_prover),
+            light_client_prover: env_vars(&env.light_client_prover),
+            bitcoin: env_vars(&env.bitcoin),
+        }
+    }
+
+    pub fn bitcoin_config(&self) -> BitcoinConfig {
+        let bitcoin = &self.bitcoin;
+        let default = BitcoinConfig::default();
+        BitcoinConfig {
+            extra_args: bitcoin.extra_args.clone().unwrap_or(default.extra_args),
+            docker_image: bitcoin.docker_image.clone().or(default.docker_image),
+            rpc_user: bitcoin.rpc_user.clone().unwrap_or(default.rpc_user),
+            rpc_password: bitcoin.rpc_password.clone().unwrap_or(default.rpc_password),
+            ..default
+        }
+    }
+
+    pub fn pruning_config(&self, kind: NodeKind) -> Option<PruningConfig> {
+        let distance = match kind {
+            NodeKind::BatchProver => self.pruning.batch_prover,
+            NodeKind::LightClientProver => self.pruning.light_client_prover,
+            NodeKind::FullNode => self.pruning.full_node,
+            NodeKind::Bitcoin | NodeKind::Sequencer => None,
+        };
+        distance.map(|distance| PruningConfig { distance })
+    }
+
+    pub fn scan_l1_start_height(&self) -> Option<u64> {
+        self.scan_l1_start_height.or(Some(1))
+    }
+
+    pub fn sequencer_config(&self) -> Result<SequencerConfig> {
+        merge(&SequencerConfig::default(), &self.sequencer).context("Invalid sequencer config")
+    }
+
+    pub fn batch_prover_config(&self) -> Result<BatchProverConfig> {
+        merge(&BatchProverConfig::default(), &self.batch_prover)
+            .context("Invalid batch prover config")
+    }
+
+    pub fn light_client_prover_config(&self) -> Result<LightClientProverConfig> {
+        merge(
+            &LightClientProverConfig::default(),
+            &self.light_client_prover,
+        )
+        .context("Invalid light client prover config")
+    }
+
+    /// Apply rollup overrides of node `kind` to the generated `rollup` config
+    pub fn rollup_config(&self, kind: NodeKind, rollup: RollupConfig) -> Result<RollupConfig> {
+        let overrides = match kind {
+            NodeKind::Sequencer => &self.rollup.sequencer,
+            NodeKind::BatchProver => &self.rollup.batch_prover,
+            NodeKind::LightClientProver => &self.rollup.light_client_prover,
+            NodeKind::FullNode => &self.rollup.full_node,
+            NodeKind::Bitcoin => return Ok(rollup),
+        };
+        merge(&rollup, overrides).with_context(|| format!("Invalid {kind} rollup config"))
+    }
+}
+
+impl KeysDefinitionConfig {
+    fn keys(&self, default: TestCaseKeys) -> Result<TestCaseKeys> {
+        let mut keys = match self.fresh {
+            Some(true) => TestCaseKeys::random(),
+            Some(false) => TestCaseKeys::well_known(),
+            None => default,
+        };
+        for (key, value) in [
+            (&mut keys.sequencer, &self.sequencer),
+            (&mut keys.sequencer_da, &self.sequencer_da),
+            (&mut keys.prover_da, &self.prover_da),
+        ] {
+            if let Some(value) = value {
+                *key =
+                    SecretKey::from_slice(&hex::decode(value)?).context("Invalid private key")?;
+            }
+        }
+        Ok(keys)
+    }
+}
+
+impl DockerDefinitionConfig {
+    fn docker_config(&self, default: TestCaseDockerConfig) -> TestCaseDockerConfig {
+        let mut config = TestCaseDockerConfig {
+            bitcoin: self.bitcoin.unwrap_or(default.bitcoin),
+            citrea: self.citrea.unwrap_or(default.citrea),
+            log_timestamps: self.log_timestamps.unwrap_or(default.log_timestamps),
+            ..default
+        };
+        for (kind, dockerized) in [
+            (NodeKind::Sequencer, self.sequencer),
+            (NodeKind::BatchProver, self.batch_prover),
+            (NodeKind::LightClientProver, self.light_client_prover),
+            (NodeKind::FullNode, self.full_node),
+        ] {
+            if let Some(dockerized) = dockerized {
+                config.nodes.insert(kind, dockerized);
+            }
+        }
+        config
+    }
+}
+
+fn env_vars(env: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    env.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+}
+
+/// Deep merge `overrides` into `base` serialized representation
+pub(crate) fn merge<T>(base: &T, overrides: &toml::Table) -> Result<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut value = toml::Value::try_from(base)?;
+    merge_value(&mut value, toml::Value::Table(overrides.clone()));
+    Ok(value.try_into()?)
+}
+
+fn merge_value(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_value(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProverGuestRunConfig;
+
+    #[test]
+    fn test_toml_definition() {
+        let definition = TestCaseDefinition::from_toml(
+            r#"
+            scan_l1_start_height = 5
+
+            [test]
+            with_batch_prover = true
+            timeout_secs = 120
+            mode = "dev-all-forks"
+
+            [docker]
+            citrea = true
+            full_node = false
+
+            [env]
+            sequencer = { RUST_LOG = "debug" }
+
+            [bitcoin]
+            extra_args = ["-txindex=0"]
+
+            [sequencer]
+            max_l2_blocks_per_commitment = 10
+
+            [batch_prover]
+            proving_mode = "skip"
+
+            [rollup.full_node.rpc]
+            max_connections = 10
+        "#,
+        )
+        .unwrap();
+
+        let test_config = definition.test_config().unwrap();
+        assert!(test_config.with_sequencer);
+        assert!(test_config.with_batch_prover);
+        assert_eq!(test_config.timeout, Duration::from_secs(120));
+        assert!(matches!(test_config.mode, CitreaMode::DevAllForks));
+        assert!(test_config.docker.is_dockerized(NodeKind::Sequencer));
+        assert!(!test_config.docker.is_dockerized(NodeKind::FullNode));
+
+        assert_eq!(definition.scan_l1_start_height(), Some(5));
+        assert_eq!(
+            definition.test_env().sequencer,
+            vec![("RUST_LOG".to_string(), "debug".to_string())]
+        );
+        assert_eq!(definition.bitcoin_config().extra_args, vec!["-txindex=0"]);
+
+        let sequencer = definition.sequencer_config().unwrap();
+        assert_eq!(sequencer.max_l2_blocks_per_commitment, 10);
+        assert_eq!(
+            sequencer.block_production_interval_ms,
+            SequencerConfig::default().block_production_interval_ms
+        );
+        assert_eq!(
+            definition.batch_prover_config().unwrap().proving_mode,
+            ProverGuestRunConfig::Skip
+        );
+
+        let rollup = definition
+            .rollup_config(NodeKind::FullNode, RollupConfig::default())
+            .unwrap();
+        assert_eq!(rollup.rpc.max_connections, 10);
+        assert_eq!(
+            rollup.rpc.max_request_body_size,
+            RollupConfig::default().rpc.max_request_body_size
+        );
+    }
+
+    #[test]
+    fn test_yaml_definition() {
+        let definition = TestCaseDefinition::from_yaml(
+            r#"
+            test:
+              n_nodes: 2
+              with_sequencer: false
+            light_client_prover:
+              initial_da_height: 15
+        "#,
+        )
+        .unwrap();
+
+        let test_config = definition.test_config().unwrap();
+        assert_eq!(test_config.n_nodes, 2);
+        assert!(!test_config.with_sequencer);
+        assert_eq!(
+            definition
+                .light_client_prover_config()
+                .unwrap()
+                .initial_da_height,
+            15
+        );
+    }
+
+    #[test]
+    fn test_invalid_definition() {
+        assert!(TestCaseDefinition::from_toml("[test]\nwith_sequencers = true").is_err());
+        assert!(
+            TestCaseDefinition::from_toml("[sequencer]\nmax_l2_blocks_per_commitment = \"4\"")
+                .unwrap()
+                .sequencer_config()
+                .is_err()
+        );
+    }
+}
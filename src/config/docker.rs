@@ -1,17 +1,117 @@
-use std::{fmt::Debug, path::PathBuf};
+use std::{env, fmt::Debug, path::PathBuf};
 
 use serde::Serialize;
 use tracing::debug;
 
-use super::{BitcoinConfig, FullL2NodeConfig};
+use super::{test_case::parse_bool_env, BitcoinConfig, FullL2NodeConfig};
 use crate::{
+    log_provider::LogPathProvider,
     node::{get_citrea_args, NodeKind},
-    utils::get_genesis_path,
+    utils::{get_citrea_path, get_genesis_path},
+    Result,
 };
 
 const DEFAULT_BITCOIN_DOCKER_IMAGE: &str = "bitcoin/bitcoin:28.0";
 const DEFAULT_CITREA_DOCKER_IMAGE: &str =
     "chainwayxyz/citrea-test:2dc23c1a7ab6f38ca597540f676134e9b957eb5d";
+const DEFAULT_CITREA_BASE_IMAGE: &str = "debian:bookworm-slim";
+const DEFAULT_CITREA_LOCAL_TAG: &str = "citrea-e2e-local:latest";
+
+/// Location of the citrea binary inside containers spawned from `CitreaImageSource::LocalBinary`
+pub(crate) const CITREA_CONTAINER_BINARY_PATH: &str = "/usr/local/bin/citrea";
+
+/// Where the citrea docker image comes from.
+#[derive(Debug, Clone, Default)]
+pub enum CitreaImageSource {
+    /// Use the tagged image, pulling it if it's missing.
+    #[default]
+    Registry,
+    /// Build the image from a local Dockerfile through the docker build API.
+    /// The build is skipped if an image tagged with `tag` already exists, remove it to force a rebuild.
+    /// Paths matched by `context`'s `.dockerignore` are left out of the build context,
+    /// `target` and `.git` are when there is none.
+    Dockerfile {
+        /// Build context directory
+        context: PathBuf,
+        /// Dockerfile path, relative to `context`
+        dockerfile: String,
+        /// Tag used to cache the built image
+        tag: String,
+    },
+    /// Bind-mount the `CITREA_E2E_TEST_BINARY` host binary into `base_image` and use it as entrypoint.
+    LocalBinary { base_image: String },
+}
+
+impl CitreaImageSource {
+    /// Resolve image source from env.
+    /// `CITREA_DOCKERFILE` takes precedence over `TEST_CITREA_DOCKER_LOCAL_BINARY`
+    pub fn from_env() -> Self {
+        if let Ok(dockerfile) = env::var("CITREA_DOCKERFILE") {
+            let dockerfile = PathBuf::from(dockerfile);
+            let context = env::var("CITREA_DOCKER_CONTEXT").map_or_else(
+                |_| {
+                    dockerfile
+                        .parent()
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| PathBuf::from("."))
+                },
+                PathBuf::from,
+            );
+            let dockerfile = dockerfile
+                .strip_prefix(&context)
+                .unwrap_or(&dockerfile)
+                .display()
+                .to_string();
+
+            return Self::Dockerfile {
+                context,
+                dockerfile,
+                tag: env::var("CITREA_DOCKER_LOCAL_TAG")
+                    .unwrap_or_else(|_| DEFAULT_CITREA_LOCAL_TAG.to_string()),
+            };
+        }
+
+        if parse_bool_env("TEST_CITREA_DOCKER_LOCAL_BINARY").unwrap_or(false) {
+            return Self::LocalBinary {
+                base_image: env::var("CITREA_DOCKER_BASE_IMAGE")
+                    .unwrap_or_else(|_| DEFAULT_CITREA_BASE_IMAGE.to_string()),
+            };
+        }
+
+        Self::Registry
+    }
+
+    /// Resolve the image to run from the configured registry `image`.
+    /// `binary` overrides `CITREA_E2E_TEST_BINARY` for `LocalBinary`.
+    pub(crate) fn resolve(&self, image: String, binary: Option<PathBuf>) -> Result<ResolvedImage> {
+        Ok(match self {
+            Self::Registry => ResolvedImage {
+                image,
+                entrypoint: None,
+                binary: None,
+            },
+            Self::Dockerfile { tag, .. } => ResolvedImage {
+                image: tag.clone(),
+                entrypoint: None,
+                binary: None,
+            },
+            Self::LocalBinary { base_image } => ResolvedImage {
+                image: base_image.clone(),
+                entrypoint: Some(vec![CITREA_CONTAINER_BINARY_PATH.to_string()]),
+                binary: Some(binary.map_or_else(get_citrea_path, Ok)?),
+            },
+        })
+    }
+}
+
+/// Image to run a container from, as resolved from a `CitreaImageSource`
+#[derive(Debug)]
+pub(crate) struct ResolvedImage {
+    pub image: String,
+    pub entrypoint: Option<Vec<String>>,
+    /// Host binary to bind-mount at `CITREA_CONTAINER_BINARY_PATH`
+    pub binary: Option<PathBuf>,
+}
 
 #[derive(Debug)]
 pub struct VolumeConfig {
@@ -21,13 +121,19 @@ pub struct VolumeConfig {
 
 #[derive(Debug)]
 pub struct DockerConfig {
+    /// Container name and hostname, suffixed with the test id. Unique per node.
+    pub name: String,
     pub ports: Vec<u16>,
     pub image: String,
     pub cmd: Vec<String>,
     pub log_path: PathBuf,
+    pub stderr_path: PathBuf,
     pub volume: VolumeConfig,
     pub host_dir: Option<Vec<String>>,
     pub kind: NodeKind,
+    pub env: Vec<(String, String)>,
+    /// Citrea binary mounted with `CitreaImageSource::LocalBinary`
+    pub binary: Option<PathBuf>,
 }
 
 impl From<&BitcoinConfig> for DockerConfig {
@@ -35,26 +141,37 @@ impl From<&BitcoinConfig> for DockerConfig {
         let mut args = config.args();
 
         // Docker specific args
-        args.extend([
-            "-rpcallowip=0.0.0.0/0".to_string(),
-            "-rpcbind=0.0.0.0".to_string(),
-            "-daemonwait=0".to_string(),
-        ]);
+        args.extend(["-rpcbind=0.0.0.0".to_string(), "-daemonwait=0".to_string()]);
+        // Only accept RPC calls from the test network, published ports included
+        args.extend(
+            config
+                .docker_subnet
+                .iter()
+                .map(|subnet| format!("-rpcallowip={subnet}")),
+        );
 
         Self {
+            // The DA node is addressed by kind, see `DockerEnv::get_hostname`
+            name: match config.idx {
+                0 => NodeKind::Bitcoin.to_string(),
+                idx => format!("{}-{idx}", NodeKind::Bitcoin),
+            },
             ports: vec![config.rpc_port, config.p2p_port],
             image: config
                 .docker_image
                 .clone()
                 .unwrap_or_else(|| DEFAULT_BITCOIN_DOCKER_IMAGE.to_string()),
             cmd: args,
-            log_path: config.data_dir.join("regtest").join("debug.log"),
+            log_path: config.log_path(),
+            stderr_path: config.stderr_path(),
             volume: VolumeConfig {
                 name: format!("bitcoin-{}", config.idx),
                 target: "/home/bitcoin/.bitcoin".to_string(),
             },
             host_dir: None,
             kind: NodeKind::Bitcoin,
+            env: config.env.clone(),
+            binary: None,
         }
     }
 }
@@ -69,25 +186,38 @@ where
         debug!("Converting config {config:?} for {kind} to docker config");
 
         let args = get_citrea_args(&config);
+        // Named after the node dir, i.e. `full-node` or `full-node-spawned-1` for additional nodes
+        let name = config.dir().file_name().map_or_else(
+            || kind.to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
 
         Self {
-            ports: vec![config.rollup.rpc.bind_port],
+            ports: vec![
+                config.rollup.rpc.bind_port,
+                config.rollup.telemetry.bind_port,
+            ],
             image: config
                 .base
                 .docker_image
                 .clone()
                 .unwrap_or(DEFAULT_CITREA_DOCKER_IMAGE.to_string()),
             cmd: args,
-            log_path: config.dir().join("stdout.log"),
+            log_path: config.log_path(),
+            stderr_path: config.stderr_path(),
+            // Storage lives on the volume so that it outlives the container, see `DockerEnv::respawn`
             volume: VolumeConfig {
-                name: format!("{kind}"),
-                target: format!("/{kind}/data"),
+                name: name.clone(),
+                target: config.rollup.storage.path.display().to_string(),
             },
             host_dir: Some(vec![
                 config.dir().to_owned().display().to_string(),
                 get_genesis_path(config.dir()),
             ]),
+            name,
             kind,
+            env: config.env(),
+            binary: config.base.binary.clone(),
         }
     }
 }
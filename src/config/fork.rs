@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+/// Fork activation heights of a running citrea node, as returned by `citrea_getForks`.
+///
+/// Forks activate at the heights compiled in for the node's network and mode,
+/// i.e. `CitreaMode::Dev` or `CitreaMode::DevAllForks`. Drives `Node::wait_for_fork`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ForkSchedule {
+    pub forks: Vec<Fork>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fork {
+    /// Fork name, as known by the citrea binary
+    pub name: String,
+    /// L2 height the fork activates at
+    pub activation_height: u64,
+}
+
+impl ForkSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Activate fork `name` at L2 `height`, replacing any previous height for this fork
+    pub fn with_fork(mut self, name: impl Into<String>, height: u64) -> Self {
+        let name = name.into();
+        self.forks.retain(|fork| fork.name != name);
+        self.forks.push(Fork {
+            name,
+            activation_height: height,
+        });
+        self.forks.sort_by_key(|fork| fork.activation_height);
+        self
+    }
+
+    pub fn activation_height(&self, name: &str) -> Option<u64> {
+        self.forks
+            .iter()
+            .find(|fork| fork.name == name)
+            .map(|fork| fork.activation_height)
+    }
+
+    /// Forks active at L2 `height`
+    pub fn active_forks(&self, height: u64) -> impl Iterator<Item = &Fork> {
+        self.forks
+            .iter()
+            .filter(move |fork| fork.activation_height <= height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_fork_schedule() {
+        let schedule = ForkSchedule::new()
+            .with_fork("fork2", 20)
+            .with_fork("fork1", 10)
+            .with_fork("fork2", 30);
+        assert_eq!(schedule.activation_height("fork2"), Some(30));
+        assert_eq!(
+            schedule
+                .active_forks(15)
+                .map(|fork| fork.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["fork1"]
+        );
+
+        // As returned by `citrea_getForks`
+        let response = json!([
+            { "name": "fork1", "activation_height": 10 },
+            { "name": "fork2", "activation_height": 30 },
+        ]);
+        assert_eq!(
+            serde_json::from_value::<ForkSchedule>(response.clone()).unwrap(),
+            schedule
+        );
+        assert_eq!(serde_json::to_value(&schedule).unwrap(), response);
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use anyhow::{bail, Context};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    utils::{get_default_genesis_path, get_workspace_root},
+    Result,
+};
+
+const EVM_FILE: &str = "evm.json";
+const ACCOUNTS_FILE: &str = "accounts.json";
+const L2_BLOCK_RULE_ENFORCER_FILE: &str = "l2_block_rule_enforcer.json";
+
+/// Genesis resources a `GenesisBuilder` starts from.
+///
+/// `BitcoinRegtest` holds the base genesis files, other presets only hold the files they change.
+#[derive(Clone, Debug, Default)]
+pub enum GenesisPreset {
+    #[default]
+    BitcoinRegtest,
+    Devnet,
+    /// Base genesis, used by mock DA test cases
+    Mock,
+    /// Base genesis, used by dockerized mock DA test cases
+    MockDockerized,
+    /// Either a relative dir from workspace root or an absolute path
+    Dir(PathBuf),
+}
+
+impl GenesisPreset {
+    pub fn dir(&self) -> PathBuf {
+        match self {
+            Self::BitcoinRegtest | Self::Mock | Self::MockDockerized => get_default_genesis_path(),
+            Self::Devnet => get_workspace_root()
+                .join("resources")
+                .join("genesis")
+                .join("devnet"),
+            Self::Dir(dir) if dir.is_absolute() => dir.clone(),
+            Self::Dir(dir) => get_workspace_root().join(dir),
+        }
+    }
+
+    /// Path of genesis file `name`, falling back to the base genesis if the preset doesn't change it
+    pub fn file(&self, name: &str) -> PathBuf {
+        let path = self.dir().join(name);
+        if path.exists() {
+            path
+        } else {
+            get_default_genesis_path().join(name)
+        }
+    }
+}
+
+/// EVM genesis account, added to `evm.json`
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EvmAccount {
+    pub address: Address,
+    pub balance: U256,
+    pub code: Bytes,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<B256, B256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+}
+
+impl EvmAccount {
+    pub fn new(address: Address, balance: U256) -> Self {
+        Self {
+            address,
+            balance,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<Bytes>) -> Self {
+        self.code = code.into();
+        self
+    }
+
+    pub fn with_storage(mut self, slot: B256, value: B256) -> Self {
+        self.storage.insert(slot, value);
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct L2BlockRuleEnforcerGenesis {
+    pub max_l2_blocks_per_l1: u64,
+    pub authority: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccountsGenesis {
+    /// Hex encoded public keys
+    pub pub_keys: Vec<String>,
+}
+
+/// Builds the genesis of a test case from a preset.
+///
+/// Fields of the preset `evm.json` are kept as is, accounts being added or replaced by address.
+#[derive(Clone, Debug)]
+pub struct GenesisBuilder {
+    evm: serde_json::Map<String, Value>,
+    accounts: AccountsGenesis,
+    l2_block_rule_enforcer: L2BlockRuleEnforcerGenesis,
+}
+
+impl GenesisBuilder {
+    pub fn from_preset(preset: GenesisPreset) -> Result<Self> {
+        let evm_file = preset.file(EVM_FILE);
+        let evm: serde_json::Map<String, Value> = read_json(&evm_file)?;
+        if !matches!(evm.get("data"), Some(Value::Array(_))) {
+            bail!("Missing accounts data in {}", evm_file.display());
+        }
+
+        Ok(Self {
+            evm,
+            accounts: read_json(&preset.file(ACCOUNTS_FILE))?,
+            l2_block_rule_enforcer: read_json(&preset.file(L2_BLOCK_RULE_ENFORCER_FILE))?,
+        })
+    }
+
+    /// Add an EVM account, replacing any preset account with the same address
+    pub fn with_account(mut self, account: EvmAccount) -> Result<Self> {
+        let address = account.address;
+        let value = serde_json::to_value(account)?;
+        let data = self.evm_data();
+        match data
+            .iter_mut()
+            .find(|entry| account_address(entry) == Some(address))
+        {
+            Some(entry) => *entry = value,
+            None => data.push(value),
+        }
+        Ok(self)
+    }
+
+    /// Add an EVM account holding `balance`
+    pub fn fund(self, address: Address, balance: U256) -> Result<Self> {
+        self.with_account(EvmAccount::new(address, balance))
+    }
+
+    pub fn with_max_l2_blocks_per_l1(mut self, max_l2_blocks_per_l1: u64) -> Self {
+        self.l2_block_rule_enforcer.max_l2_blocks_per_l1 = max_l2_blocks_per_l1;
+        self
+    }
+
+    pub fn with_authority(mut self, authority: impl Into<String>) -> Self {
+        self.l2_block_rule_enforcer.authority = authority.into();
+        self
+    }
+
+    /// Add a compressed public key to `accounts.json`
+    pub fn with_pub_key(mut self, pub_key: &[u8]) -> Self {
+        self.accounts.pub_keys.push(hex::encode(pub_key));
+        self
+    }
+
+    /// Get a preset `evm.json` field, i.e. `chain_id`
+    pub fn evm_field(&self, key: &str) -> Option<&Value> {
+        self.evm.get(key)
+    }
+
+    /// Set a `evm.json` field, i.e. `chain_id` or `block_gas_limit`
+    pub fn with_evm_field(mut self, key: impl Into<String>, value: impl Serialize) -> Result<Self> {
+        self.evm.insert(key.into(), serde_json::to_value(value)?);
+        Ok(self)
+    }
+
+    pub fn accounts(&self) -> &[Value] {
+        match self.evm.get("data") {
+            Some(Value::Array(data)) => data,
+            _ => &[],
+        }
+    }
+
+    /// Write genesis files into `dir`
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        write_json(&dir.join(EVM_FILE), &self.evm)?;
+        write_json(&dir.join(ACCOUNTS_FILE), &self.accounts)?;
+        write_json(
+            &dir.join(L2_BLOCK_RULE_ENFORCER_FILE),
+            &self.l2_block_rule_enforcer,
+        )
+    }
+
+    fn evm_data(&mut self) -> &mut Vec<Value> {
+        match self.evm.get_mut("data") {
+            Some(Value::Array(data)) => data,
+            _ => unreachable!("Checked on load"),
+        }
+    }
+}
+
+fn account_address(entry: &Value) -> Option<Address> {
+    entry.get("address")?.as_str()?.parse().ok()
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let content = serde_json::to_string_pretty(value)?;
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::address;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_genesis_builder() {
+        let preset = GenesisBuilder::from_preset(GenesisPreset::BitcoinRegtest).unwrap();
+        let n_accounts = preset.accounts().len();
+        let funded = address!("0f820f428ae436c1000b27577bf5bbf09bfec8f2");
+        let added = address!("1111111111111111111111111111111111111111");
+
+        let genesis = preset
+            .fund(funded, U256::from(10))
+            .unwrap()
+            .with_account(
+                EvmAccount::new(added, U256::from(1))
+                    .with_code(vec![0x60, 0x80])
+                    .with_storage(B256::ZERO, B256::with_last_byte(1)),
+            )
+            .unwrap()
+            .with_max_l2_blocks_per_l1(10)
+            .with_pub_key(&[2; 33]);
+
+        assert_eq!(genesis.accounts().len(), n_accounts + 1);
+
+        let dir = TempDir::new().unwrap();
+        genesis.write(dir.path()).unwrap();
+
+        let evm: Value = read_json(&dir.path().join(EVM_FILE)).unwrap();
+        assert_eq!(evm["chain_id"], 5655);
+        let data = evm["data"].as_array().unwrap();
+        let funded_entry = data
+            .iter()
+            .find(|entry| account_address(entry) == Some(funded))
+            .unwrap();
+        assert_eq!(funded_entry["balance"], "0xa");
+        let added_entry = data
+            .iter()
+            .find(|entry| account_address(entry) == Some(added))
+            .unwrap();
+        assert_eq!(added_entry["code"], "0x6080");
+
+        let rules: L2BlockRuleEnforcerGenesis =
+            read_json(&dir.path().join(L2_BLOCK_RULE_ENFORCER_FILE)).unwrap();
+        assert_eq!(rules.max_l2_blocks_per_l1, 10);
+        let accounts: AccountsGenesis = read_json(&dir.path().join(ACCOUNTS_FILE)).unwrap();
+        assert_eq!(accounts.pub_keys, vec!["02".repeat(33)]);
+    }
+
+    #[test]
+    fn test_genesis_presets() {
+        let base = GenesisBuilder::from_preset(GenesisPreset::BitcoinRegtest).unwrap();
+        let devnet = GenesisBuilder::from_preset(GenesisPreset::Devnet).unwrap();
+
+        // Devnet only changes the evm and block rules files
+        assert_ne!(devnet.accounts(), base.accounts());
+        assert_eq!(devnet.accounts.pub_keys, base.accounts.pub_keys);
+
+        for preset in [GenesisPreset::Mock, GenesisPreset::MockDockerized] {
+            let genesis = GenesisBuilder::from_preset(preset).unwrap();
+            assert_eq!(genesis.accounts(), base.accounts());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+use super::{test_case::parse_bool_env, RollupPublicKeys, SequencerConfig};
+use crate::{node::NodeKind, Result};
+
+const DEFAULT_SEQUENCER_KEY: &str =
+    "1212121212121212121212121212121212121212121212121212121212121212";
+const DEFAULT_SEQUENCER_DA_KEY: &str =
+    "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262";
+const DEFAULT_PROVER_DA_KEY: &str =
+    "56D08C2DDE7F412F80EC99A0A328F76688C904BD4D1435281EFC9270EC8C8707";
+
+/// Keys of the test case, used to populate the sequencer and DA private keys
+/// and the `RollupPublicKeys` of every node.
+///
+/// Defaults to well-known keys, or fresh ones when `TEST_FRESH_KEYS` is set.
+/// A `SequencerConfig::private_key` set by the test replaces `sequencer`, see `with_configured_sequencer_key`.
+#[derive(Clone, Debug)]
+pub struct TestCaseKeys {
+    /// L2 block signing key of the sequencer
+    pub sequencer: SecretKey,
+    /// DA signing key of the sequencer
+    pub sequencer_da: SecretKey,
+    /// DA signing key of the batch prover
+    pub prover_da: SecretKey,
+    /// Per node public keys, used as is instead of the derived ones.
+    /// Allows nodes to deliberately trust other keys, i.e. to test proof rejection.
+    pub public_keys: HashMap<NodeKind, RollupPublicKeys>,
+}
+
+impl Default for TestCaseKeys {
+    fn default() -> Self {
+        if parse_bool_env("TEST_FRESH_KEYS").unwrap_or(false) {
+            Self::random()
+        } else {
+            Self::well_known()
+        }
+    }
+}
+
+impl TestCaseKeys {
+    /// Fixed keys, matching `RollupConfig::default` public keys
+    pub fn well_known() -> Self {
+        Self::from_hex(
+            DEFAULT_SEQUENCER_KEY,
+            DEFAULT_SEQUENCER_DA_KEY,
+            DEFAULT_PROVER_DA_KEY,
+        )
+        .expect("Well-known keys are valid")
+    }
+
+    /// Generate fresh keys
+    pub fn random() -> Self {
+        Self {
+            sequencer: random_key(),
+            sequencer_da: random_key(),
+            prover_da: random_key(),
+            public_keys: HashMap::new(),
+        }
+    }
+
+    /// Use hex encoded private keys
+    pub fn from_hex(sequencer: &str, sequencer_da: &str, prover_da: &str) -> Result<Self> {
+        Ok(Self {
+            sequencer: parse_key(sequencer)?,
+            sequencer_da: parse_key(sequencer_da)?,
+            prover_da: parse_key(prover_da)?,
+            public_keys: HashMap::new(),
+        })
+    }
+
+    pub fn with_sequencer_key(mut self, key: SecretKey) -> Self {
+        self.sequencer = key;
+        self
+    }
+
+    pub fn with_sequencer_da_key(mut self, key: SecretKey) -> Self {
+        self.sequencer_da = key;
+        self
+    }
+
+    pub fn with_prover_da_key(mut self, key: SecretKey) -> Self {
+        self.prover_da = key;
+        self
+    }
+
+    /// Use `private_key` as sequencer key when the test changed it from the `SequencerConfig` default,
+    /// so that public keys are derived from the key the sequencer actually signs with
+    pub fn with_configured_sequencer_key(self, private_key: &str) -> Result<Self> {
+        if private_key == SequencerConfig::default().private_key {
+            return Ok(self);
+        }
+        Ok(self.with_sequencer_key(
+            parse_key(private_key).context("Invalid SequencerConfig private key")?,
+        ))
+    }
+
+    /// Override the public keys trusted by node `kind`
+    pub fn with_node_public_keys(mut self, kind: NodeKind, public_keys: RollupPublicKeys) -> Self {
+        self.public_keys.insert(kind, public_keys);
+        self
+    }
+
+    /// Public keys derived from the private keys
+    pub fn rollup_public_keys(&self) -> RollupPublicKeys {
+        RollupPublicKeys {
+            sequencer_public_key: public_key(&self.sequencer),
+            sequencer_da_pub_key: public_key(&self.sequencer_da),
+            prover_da_pub_key: public_key(&self.prover_da),
+        }
+    }
+
+    /// Public keys trusted by node `kind`
+    pub fn node_public_keys(&self, kind: NodeKind) -> RollupPublicKeys {
+        self.public_keys
+            .get(&kind)
+            .cloned()
+            .unwrap_or_else(|| self.rollup_public_keys())
+    }
+
+    pub fn sequencer_private_key(&self) -> String {
+        hex::encode(self.sequencer.secret_bytes())
+    }
+
+    pub fn sequencer_da_private_key(&self) -> String {
+        hex::encode(self.sequencer_da.secret_bytes())
+    }
+
+    pub fn prover_da_private_key(&self) -> String {
+        hex::encode(self.prover_da.secret_bytes())
+    }
+}
+
+/// Generate a fresh secp256k1 private key
+pub fn random_key() -> SecretKey {
+    SecretKey::new(&mut rand::thread_rng())
+}
+
+fn parse_key(key: &str) -> Result<SecretKey> {
+    Ok(SecretKey::from_slice(&hex::decode(key)?)?)
+}
+
+/// Compressed public key of `key`
+pub fn public_key(key: &SecretKey) -> Vec<u8> {
+    PublicKey::from_secret_key(&Secp256k1::signing_only(), key)
+        .serialize()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configured_sequencer_key() {
+        let keys = TestCaseKeys::random();
+        let unchanged = keys
+            .clone()
+            .with_configured_sequencer_key(&SequencerConfig::default().private_key)
+            .unwrap();
+        assert_eq!(unchanged.sequencer, keys.sequencer);
+
+        let configured = keys
+            .with_configured_sequencer_key(&"34".repeat(32))
+            .unwrap();
+        assert_eq!(configured.sequencer_private_key(), "34".repeat(32));
+        assert_eq!(
+            configured.rollup_public_keys().sequencer_public_key,
+            public_key(&configured.sequencer)
+        );
+    }
+}
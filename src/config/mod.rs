@@ -1,29 +1,56 @@
 mod bitcoin;
+mod compose;
+mod definition;
 mod docker;
+mod fork;
+mod genesis;
+mod keys;
 mod test;
 mod test_case;
 mod utils;
+mod validation;
 
 use std::{
     fmt::{self, Debug},
     path::PathBuf,
+    time::Duration,
 };
 
 pub use bitcoin::BitcoinConfig;
-pub use docker::DockerConfig;
-use serde::Serialize;
+pub use compose::{ComposeNetwork, ComposeService, DockerCompose};
+pub use definition::{
+    BitcoinDefinitionConfig, DockerDefinitionConfig, EnvDefinitionConfig, KeysDefinitionConfig,
+    PruningDefinitionConfig, RollupDefinitionConfig, TestCaseDefinition, TestCaseDefinitionConfig,
+};
+pub use docker::{CitreaImageSource, DockerConfig};
+pub(crate) use docker::{ResolvedImage, CITREA_CONTAINER_BINARY_PATH};
+pub use fork::{Fork, ForkSchedule};
+pub use genesis::{
+    AccountsGenesis, EvmAccount, GenesisBuilder, GenesisPreset, L2BlockRuleEnforcerGenesis,
+};
+pub use keys::{public_key, random_key, TestCaseKeys};
+use serde::{Deserialize, Serialize};
 pub use test::TestConfig;
+pub(crate) use test_case::{merge_env, PREVIOUS_CITREA_ENV};
 pub use test_case::{TestCaseConfig, TestCaseDockerConfig, TestCaseEnv};
 pub use utils::config_to_file;
+pub use validation::{ConfigError, ConfigErrors};
 
 pub use crate::citrea_config::{
     batch_prover::{BatchProverConfig, ProverGuestRunConfig},
     bitcoin::BitcoinServiceConfig,
     light_client_prover::LightClientProverConfig,
-    rollup::{RollupConfig, RollupPublicKeys, RpcConfig, RunnerConfig, StorageConfig},
+    rollup::{
+        PruningConfig, RollupConfig, RollupPublicKeys, RpcConfig, RunnerConfig, StorageConfig,
+        TelemetryConfig,
+    },
+    schema::{check_sample, ConfigSchema, SchemaMismatch, LATEST_CONFIG_VERSION},
     sequencer::{SequencerConfig, SequencerMempoolConfig},
 };
-use crate::{log_provider::LogPathProvider, node::NodeKind, Result};
+use crate::{
+    log_provider::LogPathProvider, node::NodeKind, traits::DEFAULT_STOP_TIMEOUT,
+    utils::get_citrea_path, Error, Result,
+};
 
 #[derive(Clone, Debug, Default)]
 pub enum DaLayer {
@@ -42,7 +69,8 @@ impl fmt::Display for DaLayer {
     }
 }
 
-#[derive(Clone, Debug, Default, Copy)]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum CitreaMode {
     #[default]
     Dev,
@@ -62,10 +90,17 @@ impl fmt::Display for CitreaMode {
 #[derive(Clone, Debug)]
 pub struct BaseNodeConfig {
     pub dir: PathBuf,
-    pub env: Vec<(&'static str, &'static str)>,
+    pub env: Vec<(String, String)>,
+    /// Args appended to the citrea args
+    pub extra_args: Vec<String>,
     pub da_layer: DaLayer,
     pub docker_image: Option<String>,
     pub mode: CitreaMode,
+    pub config_schema: Option<ConfigSchema>,
+    /// Grace period given to the node to exit on `stop` before it gets killed
+    pub stop_timeout: Duration,
+    /// Citrea binary, defaults to `CITREA_E2E_TEST_BINARY`
+    pub binary: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug)]
@@ -89,15 +124,19 @@ where
         rollup: RollupConfig,
         docker_image: Option<String>,
         dir: PathBuf,
-        env: Vec<(&'static str, &'static str)>,
+        env: Vec<(String, String)>,
         mode: CitreaMode,
     ) -> Result<Self> {
         let base = BaseNodeConfig {
             dir: dir.clone(),
             env,
+            extra_args: Vec::new(),
             da_layer: DaLayer::Bitcoin,
             docker_image,
             mode,
+            config_schema: None,
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            binary: None,
         };
 
         let conf = Self {
@@ -106,17 +145,46 @@ where
             rollup,
             kind,
         };
+        conf.write_configs()?;
 
-        // Write configs to files
-        if let Some(config) = conf.node_config() {
-            let config_path = dir.join(format!("{}_config.toml", conf.kind));
-            config_to_file(config, &config_path)?;
-        }
+        Ok(conf)
+    }
 
-        let rollup_path = dir.join(format!("{}_rollup_config.toml", conf.kind));
-        config_to_file(&conf.rollup, &rollup_path)?;
+    /// Write configs for the citrea binary expecting the config `version` samples, see `ConfigSchema`.
+    /// `None` keeps the configs as is.
+    pub fn with_config_version(mut self, version: Option<&str>) -> Result<Self> {
+        self.base.config_schema = version.map(ConfigSchema::new).transpose()?;
+        self.write_configs()?;
+        Ok(self)
+    }
 
-        Ok(conf)
+    // Write configs to files
+    pub(crate) fn write_configs(&self) -> Result<()> {
+        let dir = self.dir();
+        let config_path = dir.join(format!("{}_config.toml", self.kind));
+        let rollup_path = dir.join(format!("{}_rollup_config.toml", self.kind));
+
+        match &self.base.config_schema {
+            None => {
+                if let Some(config) = self.node_config() {
+                    config_to_file(config, &config_path)
+                        .map_err(Error::node_io(self.kind, &config_path))?;
+                }
+                config_to_file(&self.rollup, &rollup_path)
+                    .map_err(Error::node_io(self.kind, &rollup_path))?;
+            }
+            Some(schema) => {
+                if let Some(config) = self.node_config() {
+                    let config = schema.adapt_node_config(self.kind, config)?;
+                    config_to_file(&config, &config_path)
+                        .map_err(Error::node_io(self.kind, &config_path))?;
+                }
+                let rollup = schema.adapt_rollup_config(&self.rollup)?;
+                config_to_file(&rollup, &rollup_path)
+                    .map_err(Error::node_io(self.kind, &rollup_path))?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -149,10 +217,46 @@ where
         self.rollup.rpc.bind_port
     }
 
-    pub fn env(&self) -> Vec<(&'static str, &'static str)> {
+    pub fn env(&self) -> Vec<(String, String)> {
         self.base.env.clone()
     }
 
+    /// Set env `key`, overriding the test env
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.base.env = merge_env([self.base.env, vec![(key.into(), value.into())]]);
+        self
+    }
+
+    /// Run the node with `binary`, i.e. a previous release
+    pub fn with_binary(mut self, binary: impl Into<PathBuf>) -> Self {
+        self.base.binary = Some(binary.into());
+        self
+    }
+
+    /// Run the node from docker `image`, when dockerized
+    pub fn with_docker_image(mut self, image: impl Into<String>) -> Self {
+        self.base.docker_image = Some(image.into());
+        self
+    }
+
+    /// Citrea binary the node runs
+    pub fn citrea_path(&self) -> Result<PathBuf> {
+        self.base.binary.clone().map_or_else(get_citrea_path, Ok)
+    }
+
+    pub fn with_stop_timeout(mut self, stop_timeout: Duration) -> Self {
+        self.base.stop_timeout = stop_timeout;
+        self
+    }
+
+    /// Append citrea args, after the ones generated from the config
+    pub fn with_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.base
+            .extra_args
+            .extend(args.into_iter().map(Into::into));
+        self
+    }
+
     pub fn kind(&self) -> NodeKind {
         self.kind
     }
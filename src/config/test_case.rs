@@ -1,51 +1,120 @@
-use std::{env, path::PathBuf, time::Duration};
+use std::{collections::HashMap, env, path::PathBuf, time::Duration};
 
 use tempfile::TempDir;
 
-use super::CitreaMode;
-use crate::utils::generate_test_id;
+use super::{CitreaImageSource, CitreaMode, GenesisBuilder, TestCaseKeys};
+use crate::{
+    node::NodeKind,
+    resources::DEFAULT_SAMPLE_INTERVAL,
+    traits::{DEFAULT_READY_TIMEOUT, DEFAULT_STOP_TIMEOUT},
+    utils::generate_test_id,
+};
 
-#[derive(Clone, Default)]
+/// Env variables passed to the test nodes.
+///
+/// Merged in precedence order base, then test, then node, a later layer overriding the value of an earlier one.
+#[derive(Clone, Debug, Default)]
 pub struct TestCaseEnv {
-    pub test: Vec<(&'static str, &'static str)>,
-    pub full_node: Vec<(&'static str, &'static str)>,
-    pub sequencer: Vec<(&'static str, &'static str)>,
-    pub batch_prover: Vec<(&'static str, &'static str)>,
-    pub light_client_prover: Vec<(&'static str, &'static str)>,
-    pub bitcoin: Vec<(&'static str, &'static str)>,
+    pub test: Vec<(String, String)>,
+    pub full_node: Vec<(String, String)>,
+    pub sequencer: Vec<(String, String)>,
+    pub batch_prover: Vec<(String, String)>,
+    pub light_client_prover: Vec<(String, String)>,
+    pub bitcoin: Vec<(String, String)>,
 }
 
 impl TestCaseEnv {
     // Base env that should apply to every test cases
-    fn base_env() -> Vec<(&'static str, &'static str)> {
-        vec![("NO_COLOR", "1"), ("PARALLEL_PROOF_LIMIT", "1")]
+    fn base_env() -> Vec<(String, String)> {
+        vec![
+            ("NO_COLOR".to_string(), "1".to_string()),
+            ("PARALLEL_PROOF_LIMIT".to_string(), "1".to_string()),
+        ]
     }
 
-    fn test_env(&self) -> Vec<(&'static str, &'static str)> {
-        [Self::base_env(), self.test.clone()].concat()
+    /// Set `key` for every node
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.test.push((key.into(), value.into()));
+        self
     }
 
-    pub fn sequencer(&self) -> Vec<(&'static str, &'static str)> {
-        [self.test_env(), self.sequencer.clone()].concat()
+    /// Set `key` for node `kind` only
+    pub fn with_node_env(
+        mut self,
+        kind: NodeKind,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.node_env_mut(kind).push((key.into(), value.into()));
+        self
     }
 
-    pub fn batch_prover(&self) -> Vec<(&'static str, &'static str)> {
-        [self.test_env(), self.batch_prover.clone()].concat()
+    /// Merged env of node `kind`
+    pub fn node(&self, kind: NodeKind) -> Vec<(String, String)> {
+        merge_env([
+            Self::base_env(),
+            self.test.clone(),
+            self.node_env(kind).to_vec(),
+        ])
     }
 
-    pub fn light_client_prover(&self) -> Vec<(&'static str, &'static str)> {
-        [self.test_env(), self.light_client_prover.clone()].concat()
+    pub fn sequencer(&self) -> Vec<(String, String)> {
+        self.node(NodeKind::Sequencer)
     }
 
-    pub fn full_node(&self) -> Vec<(&'static str, &'static str)> {
-        [self.test_env(), self.full_node.clone()].concat()
+    pub fn batch_prover(&self) -> Vec<(String, String)> {
+        self.node(NodeKind::BatchProver)
     }
 
-    pub fn bitcoin(&self) -> Vec<(&'static str, &'static str)> {
-        [self.test_env(), self.bitcoin.clone()].concat()
+    pub fn light_client_prover(&self) -> Vec<(String, String)> {
+        self.node(NodeKind::LightClientProver)
     }
+
+    pub fn full_node(&self) -> Vec<(String, String)> {
+        self.node(NodeKind::FullNode)
+    }
+
+    pub fn bitcoin(&self) -> Vec<(String, String)> {
+        self.node(NodeKind::Bitcoin)
+    }
+
+    fn node_env(&self, kind: NodeKind) -> &[(String, String)] {
+        match kind {
+            NodeKind::Sequencer => &self.sequencer,
+            NodeKind::BatchProver => &self.batch_prover,
+            NodeKind::LightClientProver => &self.light_client_prover,
+            NodeKind::FullNode => &self.full_node,
+            NodeKind::Bitcoin => &self.bitcoin,
+        }
+    }
+
+    fn node_env_mut(&mut self, kind: NodeKind) -> &mut Vec<(String, String)> {
+        match kind {
+            NodeKind::Sequencer => &mut self.sequencer,
+            NodeKind::BatchProver => &mut self.batch_prover,
+            NodeKind::LightClientProver => &mut self.light_client_prover,
+            NodeKind::FullNode => &mut self.full_node,
+            NodeKind::Bitcoin => &mut self.bitcoin,
+        }
+    }
+}
+
+/// Merge env layers, a later layer overriding the value of an earlier one while keeping its position
+pub(crate) fn merge_env(
+    layers: impl IntoIterator<Item = Vec<(String, String)>>,
+) -> Vec<(String, String)> {
+    let mut merged: Vec<(String, String)> = Vec::new();
+    for (key, value) in layers.into_iter().flatten() {
+        match merged.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => merged.push((key, value)),
+        }
+    }
+    merged
 }
 
+pub(crate) const PREVIOUS_CITREA_ENV: &str = "CITREA_E2E_TEST_PREVIOUS_BINARY";
+
 #[derive(Clone, Debug)]
 pub struct TestCaseConfig {
     pub n_nodes: usize,
@@ -55,14 +124,31 @@ pub struct TestCaseConfig {
     pub with_light_client_prover: bool,
     pub with_citrea_cli: bool,
     pub timeout: Duration,
+    /// Grace period given to L2 nodes to exit on `stop` before they get killed
+    pub stop_timeout: Duration,
+    /// Time given to each L2 node to be ready before the test starts, defaults to `DEFAULT_READY_TIMEOUT`
+    pub ready_timeouts: HashMap<NodeKind, Duration>,
+    /// Interval L2 nodes resource usage is sampled at, defaults to `DEFAULT_SAMPLE_INTERVAL`.
+    /// Set to `None` to disable sampling.
+    pub resource_sample_interval: Option<Duration>,
     pub dir: PathBuf,
     pub docker: TestCaseDockerConfig,
     // Either a relative dir from workspace root, i.e. "./resources/genesis/devnet"
-    // Or an absolute path.
+    // Or an absolute path. Files missing from it are taken from the base genesis.
     // Defaults to resources/genesis/bitcoin-regtest
     pub genesis_dir: Option<String>,
+    // Genesis written to the test dir, takes precedence over `genesis_dir`
+    pub genesis: Option<GenesisBuilder>,
     pub test_id: String,
     pub mode: CitreaMode,
+    // Version of the vendored citrea config samples the node configs are written for,
+    // see `ConfigSchema`. Defaults to `CITREA_CONFIG_VERSION` env, or latest.
+    pub citrea_config_version: Option<String>,
+    pub keys: TestCaseKeys,
+    /// Citrea binary per node, defaults to `CITREA_E2E_TEST_BINARY`
+    pub citrea_binaries: HashMap<NodeKind, PathBuf>,
+    /// Previous citrea release, for upgrade tests. Defaults to `CITREA_E2E_TEST_PREVIOUS_BINARY` env
+    pub previous_citrea_binary: Option<PathBuf>,
 }
 
 impl Default for TestCaseConfig {
@@ -76,6 +162,9 @@ impl Default for TestCaseConfig {
             with_full_node: false,
             with_citrea_cli: false,
             timeout: Duration::from_secs(60),
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            ready_timeouts: HashMap::new(),
+            resource_sample_interval: Some(DEFAULT_SAMPLE_INTERVAL),
             dir: std::env::var("TEST_OUT_DIR")
                 .map_or_else(
                     |_| {
@@ -88,16 +177,37 @@ impl Default for TestCaseConfig {
                 .join(test_id.clone()),
             docker: TestCaseDockerConfig::default(),
             genesis_dir: None,
+            genesis: None,
             test_id,
             mode: CitreaMode::Dev,
+            citrea_config_version: env::var("CITREA_CONFIG_VERSION").ok(),
+            keys: TestCaseKeys::default(),
+            citrea_binaries: HashMap::new(),
+            previous_citrea_binary: env::var(PREVIOUS_CITREA_ENV).ok().map(PathBuf::from),
         }
     }
 }
 
+impl TestCaseConfig {
+    /// Time given to node `kind` to be ready
+    pub fn ready_timeout(&self, kind: NodeKind) -> Duration {
+        self.ready_timeouts
+            .get(&kind)
+            .copied()
+            .unwrap_or(DEFAULT_READY_TIMEOUT)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TestCaseDockerConfig {
     pub bitcoin: bool,
+    /// Default placement of citrea nodes
     pub citrea: bool,
+    /// Per node placement, takes precedence over `bitcoin` and `citrea`
+    pub nodes: HashMap<NodeKind, bool>,
+    pub citrea_image: CitreaImageSource,
+    /// Prefix container log lines with docker timestamps
+    pub log_timestamps: bool,
 }
 
 impl Default for TestCaseDockerConfig {
@@ -105,13 +215,43 @@ impl Default for TestCaseDockerConfig {
         TestCaseDockerConfig {
             bitcoin: parse_bool_env("TEST_BITCOIN_DOCKER").unwrap_or(true),
             citrea: parse_bool_env("TEST_CITREA_DOCKER").unwrap_or(false),
+            nodes: [
+                NodeKind::Sequencer,
+                NodeKind::BatchProver,
+                NodeKind::LightClientProver,
+                NodeKind::FullNode,
+            ]
+            .into_iter()
+            .filter_map(|kind| {
+                // i.e. TEST_BATCH_PROVER_DOCKER
+                let key =
+                    format!("TEST_{}_DOCKER", kind.to_string().replace('-', "_")).to_uppercase();
+                parse_bool_env(&key).map(|dockerized| (kind, dockerized))
+            })
+            .collect(),
+            citrea_image: CitreaImageSource::from_env(),
+            log_timestamps: parse_bool_env("TEST_DOCKER_LOG_TIMESTAMPS").unwrap_or(false),
         }
     }
 }
 
 impl TestCaseDockerConfig {
     pub fn enabled(&self) -> bool {
-        self.bitcoin || self.citrea
+        self.bitcoin || self.citrea || self.nodes.values().any(|&dockerized| dockerized)
+    }
+
+    /// Whether node `kind` runs in docker
+    pub fn is_dockerized(&self, kind: NodeKind) -> bool {
+        self.nodes.get(&kind).copied().unwrap_or(match kind {
+            NodeKind::Bitcoin => self.bitcoin,
+            _ => self.citrea,
+        })
+    }
+
+    /// Override placement of node `kind`
+    pub fn with_node(mut self, kind: NodeKind, dockerized: bool) -> Self {
+        self.nodes.insert(kind, dockerized);
+        self
     }
 }
 
@@ -120,3 +260,49 @@ pub fn parse_bool_env(key: &str) -> Option<bool> {
         .ok()
         .map(|v| &v == "1" || &v.to_lowercase() == "true")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BitcoinConfig;
+
+    #[test]
+    fn test_env_precedence() {
+        let env = TestCaseEnv::default()
+            .with_env("RUST_LOG", "info")
+            .with_env("NO_COLOR", "0")
+            .with_node_env(NodeKind::Sequencer, "RUST_LOG", "debug")
+            .with_node_env(NodeKind::Sequencer, "PORT", 1234.to_string());
+
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+        assert_eq!(
+            env.sequencer(),
+            vec![
+                pair("NO_COLOR", "0"),
+                pair("PARALLEL_PROOF_LIMIT", "1"),
+                pair("RUST_LOG", "debug"),
+                pair("PORT", "1234"),
+            ]
+        );
+        assert_eq!(
+            env.full_node(),
+            vec![
+                pair("NO_COLOR", "0"),
+                pair("PARALLEL_PROOF_LIMIT", "1"),
+                pair("RUST_LOG", "info"),
+            ]
+        );
+
+        // Bitcoin node env, as set with `BitcoinConfig::with_env`, overrides the test env
+        let env = env.with_node_env(NodeKind::Bitcoin, "NO_COLOR", "1");
+        let bitcoin = BitcoinConfig::default().with_env("RUST_LOG", "warn");
+        assert_eq!(
+            merge_env([env.bitcoin(), bitcoin.env]),
+            vec![
+                pair("NO_COLOR", "1"),
+                pair("PARALLEL_PROOF_LIMIT", "1"),
+                pair("RUST_LOG", "warn"),
+            ]
+        );
+    }
+}
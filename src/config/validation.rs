@@ -0,0 +1,251 @@
+use std::{collections::HashMap, fmt};
+
+use bitcoin::secp256k1::SecretKey;
+
+use super::{public_key, FullL2NodeConfig, TestConfig};
+use crate::{framework::expected_initial_da_height, node::NodeKind, Error, Result};
+
+/// Inconsistent config value, `path` being the field path within `TestConfig`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Every problem found while validating a `TestConfig`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid test config ({} errors)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl TestConfig {
+    /// Check cross-node invariants before any node is spawned.
+    /// Public keys are derived from the configured private keys and compared against
+    /// the `public_keys` of every enabled node.
+    /// Fails with `Error::Config` listing every problem found.
+    pub fn validate(&self) -> Result<()> {
+        let errors = self.validation_errors();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(ConfigErrors(errors)).into())
+        }
+    }
+
+    pub fn validation_errors(&self) -> Vec<ConfigError> {
+        let mut errors = Validator::default();
+        let test_case = &self.test_case;
+
+        if test_case.with_batch_prover && !test_case.with_sequencer {
+            errors.push(
+                "test_case.with_batch_prover",
+                "batch prover requires with_sequencer",
+            );
+        }
+        if test_case.with_full_node && !test_case.with_sequencer {
+            errors.push(
+                "test_case.with_full_node",
+                "full node requires with_sequencer",
+            );
+        }
+
+        let sequencer_public_key = errors.derive_public_key(
+            "sequencer.node.private_key",
+            &self.sequencer.node.private_key,
+        );
+        let sequencer_da_pub_key =
+            errors.derive_da_public_key(&self.sequencer, test_case.with_sequencer);
+        let prover_da_pub_key =
+            errors.derive_da_public_key(&self.batch_prover, test_case.with_batch_prover);
+
+        let mut ports: HashMap<u16, NodeKind> = HashMap::new();
+        for (enabled, kind, rollup) in [
+            (
+                test_case.with_sequencer,
+                self.sequencer.kind(),
+                &self.sequencer.rollup,
+            ),
+            (
+                test_case.with_batch_prover,
+                self.batch_prover.kind(),
+                &self.batch_prover.rollup,
+            ),
+            (
+                test_case.with_light_client_prover,
+                self.light_client_prover.kind(),
+                &self.light_client_prover.rollup,
+            ),
+            (
+                test_case.with_full_node,
+                self.full_node.kind(),
+                &self.full_node.rollup,
+            ),
+        ] {
+            if !enabled {
+                continue;
+            }
+
+            for (path, port) in [
+                ("rpc.bind_port", rollup.rpc.bind_port),
+                ("telemetry.bind_port", rollup.telemetry.bind_port),
+            ] {
+                // Port 0 binds a random port
+                if port == 0 {
+                    continue;
+                }
+                if let Some(other) = ports.insert(port, kind) {
+                    errors.push(
+                        format!("{kind}.rollup.{path}"),
+                        format!("port {port} is already used by {other}"),
+                    );
+                }
+            }
+
+            // Deliberately mismatching keys
+            if test_case.keys.public_keys.contains_key(&kind) {
+                continue;
+            }
+
+            let keys = &rollup.public_keys;
+            for (field, configured, expected) in [
+                (
+                    "sequencer_public_key",
+                    &keys.sequencer_public_key,
+                    &sequencer_public_key,
+                ),
+                (
+                    "sequencer_da_pub_key",
+                    &keys.sequencer_da_pub_key,
+                    &sequencer_da_pub_key,
+                ),
+                (
+                    "prover_da_pub_key",
+                    &keys.prover_da_pub_key,
+                    &prover_da_pub_key,
+                ),
+            ] {
+                if let Some(expected) = expected {
+                    if configured != expected {
+                        errors.push(
+                            format!("{kind}.rollup.public_keys.{field}"),
+                            format!(
+                                "{} does not match the configured private key, expected {}",
+                                hex::encode(configured),
+                                hex::encode(expected)
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        if test_case.with_light_client_prover {
+            let initial_da_height = self.light_client_prover.node.initial_da_height;
+            let tip = expected_initial_da_height(test_case);
+            if initial_da_height > tip {
+                errors.push(
+                    "light_client_prover.node.initial_da_height",
+                    format!("{initial_da_height} is above the chain tip {tip} at node startup"),
+                );
+            }
+        }
+
+        errors.0
+    }
+}
+
+#[derive(Default)]
+struct Validator(Vec<ConfigError>);
+
+impl Validator {
+    fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ConfigError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    // Derive compressed public key from hex encoded private key
+    fn derive_public_key(&mut self, path: &str, private_key: &str) -> Option<Vec<u8>> {
+        let secret_key = hex::decode(private_key)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| SecretKey::from_slice(&bytes).map_err(|e| e.to_string()));
+        match secret_key {
+            Ok(secret_key) => Some(public_key(&secret_key)),
+            Err(e) => {
+                self.push(path, format!("invalid private key: {e}"));
+                None
+            }
+        }
+    }
+
+    // DA public key of a node signing DA transactions
+    fn derive_da_public_key<T>(
+        &mut self,
+        config: &FullL2NodeConfig<T>,
+        enabled: bool,
+    ) -> Option<Vec<u8>>
+    where
+        T: Clone + fmt::Debug + serde::Serialize + Send + Sync,
+    {
+        let path = format!("{}.rollup.da.da_private_key", config.kind());
+        match (&config.rollup.da.da_private_key, enabled) {
+            (Some(private_key), _) => self.derive_public_key(&path, private_key),
+            (None, true) => {
+                self.push(path, "required to sign DA transactions");
+                None
+            }
+            (None, false) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RollupConfig, SequencerConfig};
+
+    #[test]
+    fn test_default_public_keys() {
+        let mut validator = Validator::default();
+        let keys = RollupConfig::default().public_keys;
+
+        assert_eq!(
+            validator.derive_public_key("", &SequencerConfig::default().private_key),
+            Some(keys.sequencer_public_key)
+        );
+        assert_eq!(
+            validator.derive_public_key(
+                "",
+                "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262"
+            ),
+            Some(keys.sequencer_da_pub_key)
+        );
+        assert_eq!(
+            validator.derive_public_key(
+                "",
+                "56D08C2DDE7F412F80EC99A0A328F76688C904BD4D1435281EFC9270EC8C8707"
+            ),
+            Some(keys.prover_da_pub_key)
+        );
+        assert_eq!(validator.derive_public_key("key", "12"), None);
+        assert_eq!(validator.0.len(), 1);
+        assert_eq!(validator.0[0].path, "key");
+    }
+}
@@ -1,25 +1,67 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context, Result};
 use bollard::{
-    container::{Config, LogOutput, LogsOptions, NetworkingConfig},
-    image::CreateImageOptions,
-    models::{EndpointSettings, Mount, PortBinding},
-    network::CreateNetworkOptions,
-    secret::MountTypeEnum,
-    service::HostConfig,
-    volume::CreateVolumeOptions,
+    body_try_stream,
+    container::LogOutput,
+    models::{
+        ContainerCreateBody, EndpointSettings, EventMessage, HostConfig, Mount, MountTypeEnum,
+        NetworkCreateRequest, NetworkingConfig, PortBinding, VolumeCreateOptions,
+    },
+    query_parameters::{
+        BuildImageOptions, CreateContainerOptions, CreateImageOptions, EventsOptions,
+        InspectContainerOptions, InspectNetworkOptions, ListContainersOptions, ListImagesOptions,
+        LogsOptions, RemoveContainerOptions, RemoveVolumeOptions, StartContainerOptions,
+        StopContainerOptions,
+    },
     Docker,
 };
-use futures::StreamExt;
-use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex, task::JoinHandle};
-use tracing::{debug, error, info};
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{mpsc, watch, Mutex},
+    task::JoinHandle,
+    time::sleep,
+};
+use tracing::{debug, error, info, trace};
 
 use super::{config::DockerConfig, traits::SpawnOutput, utils::generate_test_id};
-use crate::{config::TestCaseDockerConfig, node::NodeKind};
+use crate::{
+    config::{
+        CitreaImageSource, ResolvedImage, TestCaseDockerConfig, CITREA_CONTAINER_BINARY_PATH,
+    },
+    node::NodeKind,
+    supervisor::log_tails,
+    Error,
+};
+
+/// Hostname under which containers reach the host
+pub(crate) const DOCKER_HOST_GATEWAY: &str = "host.docker.internal";
+
+// Label used to scope docker events to the containers of a single test
+const TEST_ID_LABEL: &str = "citrea-e2e.test-id";
+
+const EVENTS_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Prevents concurrent test cases from building the same local image, keyed by tag
+static IMAGE_BUILD_LOCKS: LazyLock<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+// Size of the chunks the build context is streamed in, and how many can be in flight
+const BUILD_CONTEXT_CHUNK_SIZE: usize = 1024 * 1024;
+const BUILD_CONTEXT_CHANNEL_SIZE: usize = 8;
+
+// Excluded from build contexts that don't have a `.dockerignore`
+const DEFAULT_BUILD_CONTEXT_EXCLUDES: [&str; 2] = ["/target", "/.git"];
 
 #[derive(Debug)]
 pub struct ContainerSpawnOutput {
@@ -27,10 +69,49 @@ pub struct ContainerSpawnOutput {
     pub ip: String,
 }
 
+/// Container exit as reported by docker `die` events
+#[derive(Debug, Clone)]
+pub struct ContainerExit {
+    pub id: String,
+    pub kind: NodeKind,
+    pub exit_code: Option<i64>,
+    pub oom_killed: bool,
+    /// Whether the exit followed a requested stop or restart
+    pub requested: bool,
+    pub log_path: PathBuf,
+    pub stderr_path: PathBuf,
+}
+
+impl fmt::Display for ContainerExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} container {} exited", self.kind, self.id)?;
+        if let Some(exit_code) = self.exit_code {
+            write!(f, " with exit code {exit_code}")?;
+        }
+        if self.oom_killed {
+            write!(f, " (OOM killed)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ContainerState {
+    kind: NodeKind,
+    log_path: PathBuf,
+    stderr_path: PathBuf,
+    stop_requested: bool,
+    oom_killed: bool,
+    restarts: u32,
+    exit: Option<ContainerExit>,
+}
+
 #[derive(Debug, Clone)]
 pub struct NetworkInfo {
     id: String,
     name: String,
+    /// IPv4 subnet of the test network
+    pub subnet: String,
 }
 
 pub struct DockerEnv {
@@ -38,7 +119,9 @@ pub struct DockerEnv {
     pub network_info: NetworkInfo,
     id: String,
     volumes: Mutex<HashSet<String>>,
-    container_ids: Mutex<HashSet<String>>,
+    containers: Arc<Mutex<HashMap<String, ContainerState>>>,
+    unexpected_exit: Arc<watch::Sender<Option<ContainerExit>>>,
+    events_handle: JoinHandle<()>,
     test_case_config: TestCaseDockerConfig,
 }
 
@@ -49,12 +132,23 @@ impl DockerEnv {
         let test_id = generate_test_id();
         let network_info = Self::create_network(&docker, &test_id).await?;
 
+        let containers = Arc::new(Mutex::new(HashMap::new()));
+        let unexpected_exit = Arc::new(watch::Sender::new(None));
+        let events_handle = Self::watch_container_events(
+            docker.clone(),
+            &test_id,
+            Arc::clone(&containers),
+            Arc::clone(&unexpected_exit),
+        );
+
         Ok(Self {
             docker,
             network_info,
             id: test_id,
             volumes: Mutex::new(HashSet::new()),
-            container_ids: Mutex::new(HashSet::new()),
+            containers,
+            unexpected_exit,
+            events_handle,
             test_case_config,
         })
     }
@@ -64,11 +158,10 @@ impl DockerEnv {
     async fn create_volume(&self, config: &DockerConfig) -> Result<()> {
         let volume_name = format!("{}-{}", config.volume.name, self.id);
         self.docker
-            .create_volume(CreateVolumeOptions {
-                name: volume_name.clone(),
-                driver: "local".to_string(),
-                driver_opts: HashMap::new(),
-                labels: HashMap::new(),
+            .create_volume(VolumeCreateOptions {
+                name: Some(volume_name.clone()),
+                driver: Some("local".to_string()),
+                ..Default::default()
             })
             .await?;
 
@@ -80,27 +173,40 @@ impl DockerEnv {
     /// Create a new test network and return its network, name and id
     async fn create_network(docker: &Docker, test_case_id: &str) -> Result<NetworkInfo> {
         let network_name = format!("test_network_{test_case_id}");
-        let options = CreateNetworkOptions {
+        let options = NetworkCreateRequest {
             name: network_name.clone(),
-            check_duplicate: true,
-            driver: "bridge".to_string(),
+            driver: Some("bridge".to_string()),
             ..Default::default()
         };
 
-        let id = docker
-            .create_network(options)
+        let id = docker.create_network(options).await?.id;
+
+        let subnet = docker
+            .inspect_network(&id, None::<InspectNetworkOptions>)
             .await?
-            .id
-            .context("Error getting network id")?;
+            .ipam
+            .and_then(|ipam| ipam.config)
+            .into_iter()
+            .flatten()
+            .filter_map(|config| config.subnet)
+            .find(|subnet| subnet.contains('.'))
+            .context("Error getting network subnet")?;
 
         Ok(NetworkInfo {
             id,
             name: network_name,
+            subnet,
         })
     }
 
+    /// Hostname of the node of kind `kind` started with the test
     pub fn get_hostname(&self, kind: &NodeKind) -> String {
-        format!("{kind}-{}", self.id)
+        self.container_name(&kind.to_string())
+    }
+
+    // Container name, and hostname, of the node named `name`, see `DockerConfig::name`
+    fn container_name(&self, name: &str) -> String {
+        format!("{name}-{}", self.id)
     }
 
     pub async fn spawn(&self, config: DockerConfig) -> Result<SpawnOutput> {
@@ -121,18 +227,19 @@ impl DockerEnv {
                 (
                     format!("{port}/tcp"),
                     Some(vec![PortBinding {
-                        host_ip: Some("0.0.0.0".to_string()),
+                        host_ip: Some("127.0.0.1".to_string()),
                         host_port: Some(port.to_string()),
                     }]),
                 )
             })
             .collect();
 
+        let container_name = self.container_name(&config.name);
         let mut network_config = HashMap::new();
         network_config.insert(
             self.network_info.id.clone(),
             EndpointSettings {
-                ip_address: Some(self.get_hostname(&config.kind)),
+                ip_address: Some(container_name.clone()),
                 ..Default::default()
             },
         );
@@ -156,21 +263,57 @@ impl DockerEnv {
             }
         }
 
-        let container_config = Config {
-            hostname: Some(format!("{}-{}", config.kind, self.id)),
-            image: Some(config.image),
+        let image_source = match config.kind {
+            NodeKind::Bitcoin => &CitreaImageSource::Registry,
+            _ => &self.test_case_config.citrea_image,
+        };
+
+        let ResolvedImage {
+            image,
+            entrypoint,
+            binary,
+        } = image_source.resolve(config.image, config.binary)?;
+
+        if let Some(binary) = binary {
+            mounts.push(Mount {
+                target: Some(CITREA_CONTAINER_BINARY_PATH.to_string()),
+                source: Some(binary.display().to_string()),
+                typ: Some(MountTypeEnum::BIND),
+                read_only: Some(true),
+                ..Default::default()
+            });
+        }
+
+        let labels = HashMap::from([
+            (TEST_ID_LABEL.to_string(), self.id.clone()),
+            ("citrea-e2e.kind".to_string(), config.kind.to_string()),
+        ]);
+
+        let container_config = ContainerCreateBody {
+            hostname: Some(container_name.clone()),
+            labels: Some(labels),
+            image: Some(image),
+            entrypoint,
             cmd: Some(config.cmd),
             exposed_ports: Some(exposed_ports),
-            env: Some(vec!["PARALLEL_PROOF_LIMIT=1".to_string()]), // Todo proper env handling
+            env: Some(
+                config
+                    .env
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect(),
+            ),
             host_config: Some(HostConfig {
                 port_bindings: Some(port_bindings),
                 mounts: Some(mounts),
+                extra_hosts: Some(vec![format!("{DOCKER_HOST_GATEWAY}:host-gateway")]),
                 ..Default::default()
             }),
             networking_config: Some(NetworkingConfig {
-                endpoints_config: network_config,
+                endpoints_config: Some(network_config),
             }),
-            tty: Some(true),
+            // Keep stdout and stderr demultiplexed
+            tty: Some(false),
             ..Default::default()
         };
 
@@ -178,22 +321,45 @@ impl DockerEnv {
             .image
             .as_ref()
             .context("Image not specified in config")?;
-        self.ensure_image_exists(image).await?;
+        self.ensure_image_exists(image, image_source).await?;
 
         let container = self
             .docker
-            .create_container::<String, String>(None, container_config)
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: Some(container_name),
+                    ..Default::default()
+                }),
+                container_config,
+            )
             .await
-            .map_err(|e| anyhow!("Failed to create Docker container {e}"))?;
+            .map_err(Error::docker(config.kind))
+            .context("Failed to create Docker container")?;
 
-        self.container_ids.lock().await.insert(container.id.clone());
+        self.containers.lock().await.insert(
+            container.id.clone(),
+            ContainerState {
+                kind: config.kind,
+                log_path: config.log_path.clone(),
+                stderr_path: config.stderr_path.clone(),
+                stop_requested: false,
+                oom_killed: false,
+                restarts: 0,
+                exit: None,
+            },
+        );
 
         self.docker
-            .start_container::<String>(&container.id, None)
+            .start_container(&container.id, None::<StartContainerOptions>)
             .await
+            .map_err(Error::docker(config.kind))
             .context("Failed to start Docker container")?;
 
-        let inspect_result = self.docker.inspect_container(&container.id, None).await?;
+        let inspect_result = self
+            .docker
+            .inspect_container(&container.id, None::<InspectContainerOptions>)
+            .await
+            .map_err(Error::docker(config.kind))?;
         let ip_address = inspect_result
             .network_settings
             .and_then(|ns| ns.networks)
@@ -212,6 +378,8 @@ impl DockerEnv {
             self.docker.clone(),
             container.id.clone(),
             config.log_path,
+            config.stderr_path,
+            self.test_case_config.log_timestamps,
             &config.kind,
         );
 
@@ -223,22 +391,47 @@ impl DockerEnv {
         Ok(spawn_output)
     }
 
-    async fn ensure_image_exists(&self, image: &str) -> Result<()> {
+    async fn image_exists(&self, image: &str) -> Result<bool> {
         let images = self
             .docker
-            .list_images::<String>(None)
+            .list_images(None::<ListImagesOptions>)
             .await
             .context("Failed to list Docker images")?;
-        if images
+        Ok(images
             .iter()
-            .any(|img| img.repo_tags.contains(&image.to_string()))
-        {
+            .any(|img| img.repo_tags.contains(&image.to_string())))
+    }
+
+    async fn ensure_image_exists(&self, image: &str, source: &CitreaImageSource) -> Result<()> {
+        if self.image_exists(image).await? {
             return Ok(());
         }
 
+        if let CitreaImageSource::Dockerfile {
+            context,
+            dockerfile,
+            tag,
+        } = source
+        {
+            let lock = Arc::clone(
+                IMAGE_BUILD_LOCKS
+                    .lock()
+                    .unwrap()
+                    .entry(tag.clone())
+                    .or_default(),
+            );
+            let _guard = lock.lock().await;
+
+            // Built by a concurrent test case while waiting on the lock
+            if self.image_exists(image).await? {
+                return Ok(());
+            }
+            return self.build_image(context, dockerfile, tag).await;
+        }
+
         info!("Pulling image: {image}...");
         let options = Some(CreateImageOptions {
-            from_image: image,
+            from_image: Some(image.to_string()),
             ..Default::default()
         });
 
@@ -250,7 +443,7 @@ impl DockerEnv {
                         info!("\r{status}: {progress}     ");
                     }
                 }
-                Err(e) => return Err(anyhow::anyhow!("Failed to pull image: {}", e)),
+                Err(e) => return Err(Error::from(e)).context("Failed to pull image"),
             }
         }
         info!("Image succesfully pulled");
@@ -258,21 +451,93 @@ impl DockerEnv {
         Ok(())
     }
 
+    async fn build_image(&self, context: &Path, dockerfile: &str, tag: &str) -> Result<()> {
+        info!(
+            "Building image {tag} from {} with context {}...",
+            dockerfile,
+            context.display()
+        );
+
+        // The context is archived on a blocking thread and streamed to docker as it's produced
+        let (tx, rx) = mpsc::channel(BUILD_CONTEXT_CHANNEL_SIZE);
+        let archive = {
+            let context = context.to_path_buf();
+            let dockerfile = dockerfile.to_string();
+            tokio::task::spawn_blocking(move || {
+                let writer =
+                    io::BufWriter::with_capacity(BUILD_CONTEXT_CHUNK_SIZE, BodyWriter(tx.clone()));
+                match archive_build_context(&context, &dockerfile, writer) {
+                    // Abort the build request rather than sending a truncated context
+                    Err(e) if !tx.is_closed() => {
+                        let _ = tx.blocking_send(Err(io::Error::other(format!("{e:#}"))));
+                        Err(e)
+                    }
+                    // The build request ended first and reports its own failure
+                    _ => Ok(()),
+                }
+            })
+        };
+        let body = body_try_stream(stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        }));
+
+        let options = BuildImageOptions {
+            dockerfile: dockerfile.to_string(),
+            t: Some(tag.to_string()),
+            rm: true,
+            ..Default::default()
+        };
+
+        let build = async {
+            let mut stream = self.docker.build_image(options, None, Some(body));
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(info) => {
+                        if let Some(error) = info.error {
+                            return Err(anyhow!("Failed to build image {tag}: {error}"));
+                        }
+                        if let Some(line) = info.stream {
+                            debug!("{}", line.trim_end());
+                        }
+                    }
+                    Err(e) => return Err(anyhow!("Failed to build image {tag}: {e}")),
+                }
+            }
+            Ok(())
+        }
+        .await;
+
+        archive
+            .await?
+            .with_context(|| format!("Failed to archive {}", context.display()))?;
+        build?;
+        info!("Image {tag} succesfully built");
+
+        Ok(())
+    }
+
     pub async fn cleanup(&self) -> Result<()> {
-        for id in self.container_ids.lock().await.iter() {
+        for id in self.containers.lock().await.keys() {
             debug!("Logs for container {}:", id);
             let _ = self.dump_logs_cli(id);
         }
 
-        let containers = self.docker.list_containers::<String>(None).await?;
+        let containers = self
+            .docker
+            .list_containers(None::<ListContainersOptions>)
+            .await?;
         for container in containers {
             if let (Some(id), Some(networks)) = (
                 container.id,
                 container.network_settings.and_then(|ns| ns.networks),
             ) {
                 if networks.contains_key(&self.network_info.name) {
-                    self.docker.stop_container(&id, None).await?;
-                    self.docker.remove_container(&id, None).await?;
+                    self.docker
+                        .stop_container(&id, None::<StopContainerOptions>)
+                        .await?;
+                    self.docker
+                        .remove_container(&id, None::<RemoveContainerOptions>)
+                        .await?;
                 }
             }
         }
@@ -280,45 +545,221 @@ impl DockerEnv {
         self.docker.remove_network(&self.network_info.name).await?;
 
         for volume_name in self.volumes.lock().await.iter() {
-            self.docker.remove_volume(volume_name, None).await?;
+            self.docker
+                .remove_volume(volume_name, None::<RemoveVolumeOptions>)
+                .await?;
         }
         Ok(())
     }
 
+    /// Watch lifecycle events of the test containers and keep track of their exit status.
+    /// Exits that don't follow a `kill`/`stop` event or a call to `expect_exit` are reported as unexpected.
+    /// The event stream is reopened from the last seen event when it fails or ends.
+    fn watch_container_events(
+        docker: Docker,
+        test_id: &str,
+        containers: Arc<Mutex<HashMap<String, ContainerState>>>,
+        unexpected_exit: Arc<watch::Sender<Option<ContainerExit>>>,
+    ) -> JoinHandle<()> {
+        let filters = HashMap::from([
+            ("type".to_string(), vec!["container".to_string()]),
+            (
+                "label".to_string(),
+                vec![format!("{TEST_ID_LABEL}={test_id}")],
+            ),
+        ]);
+
+        tokio::spawn(async move {
+            // Nanosecond timestamp of the last handled event
+            let mut last_event: Option<i64> = None;
+
+            loop {
+                let mut events = docker.events(Some(EventsOptions {
+                    since: last_event.map(|nanos| {
+                        format!("{}.{:09}", nanos / 1_000_000_000, nanos % 1_000_000_000)
+                    }),
+                    filters: Some(filters.clone()),
+                    ..Default::default()
+                }));
+
+                while let Some(event) = events.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            error!("Docker event stream failed, reconnecting: {e}");
+                            break;
+                        }
+                    };
+
+                    // Events replayed after reconnecting
+                    if let (Some(time), Some(last)) = (event.time_nano, last_event) {
+                        if time <= last {
+                            continue;
+                        }
+                    }
+                    last_event = event.time_nano.or(last_event);
+
+                    Self::handle_container_event(event, &containers, &unexpected_exit).await;
+                }
+
+                sleep(EVENTS_RECONNECT_INTERVAL).await;
+            }
+        })
+    }
+
+    async fn handle_container_event(
+        event: EventMessage,
+        containers: &Mutex<HashMap<String, ContainerState>>,
+        unexpected_exit: &watch::Sender<Option<ContainerExit>>,
+    ) {
+        let (Some(action), Some(actor)) = (event.action, event.actor) else {
+            return;
+        };
+        let Some(id) = actor.id else {
+            return;
+        };
+
+        let mut containers = containers.lock().await;
+        let Some(state) = containers.get_mut(&id) else {
+            return;
+        };
+
+        trace!("{} container {id} event: {action}", state.kind);
+        match action.as_str() {
+            "kill" | "stop" => state.stop_requested = true,
+            "oom" => state.oom_killed = true,
+            "restart" => state.restarts += 1,
+            "start" => {
+                state.stop_requested = false;
+                state.oom_killed = false;
+                state.exit = None;
+            }
+            "die" => {
+                let exit = ContainerExit {
+                    id: id.clone(),
+                    kind: state.kind,
+                    exit_code: actor
+                        .attributes
+                        .as_ref()
+                        .and_then(|attributes| attributes.get("exitCode"))
+                        .and_then(|code| code.parse().ok()),
+                    oom_killed: state.oom_killed,
+                    requested: state.stop_requested,
+                    log_path: state.log_path.clone(),
+                    stderr_path: state.stderr_path.clone(),
+                };
+
+                if exit.requested {
+                    debug!("{exit}");
+                } else {
+                    error!("{exit} unexpectedly");
+                    unexpected_exit.send_replace(Some(exit.clone()));
+                }
+                state.exit = Some(exit);
+            }
+            _ => {}
+        }
+    }
+
+    /// Recreate container `id` from `config` on the same volume, keeping the node storage.
+    /// Used to restart a node with other args or another image.
+    pub async fn respawn(&self, id: &str, config: DockerConfig) -> Result<SpawnOutput> {
+        self.expect_exit(id).await;
+        self.docker
+            .remove_container(
+                id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .map_err(Error::docker(config.kind))
+            .context("Failed to remove Docker container")?;
+        self.containers.lock().await.remove(id);
+
+        self.spawn(config).await
+    }
+
+    /// Flag the next exit of container `id` as requested.
+    /// Required when a container is shut down from within, i.e. by a `stop` RPC call.
+    pub async fn expect_exit(&self, id: &str) {
+        if let Some(state) = self.containers.lock().await.get_mut(id) {
+            state.stop_requested = true;
+        }
+    }
+
+    /// Returns the last exit of container `id`, if it exited
+    pub async fn container_exit(&self, id: &str) -> Option<ContainerExit> {
+        self.containers
+            .lock()
+            .await
+            .get(id)
+            .and_then(|state| state.exit.clone())
+    }
+
+    /// Returns the number of times container `id` was restarted
+    pub async fn container_restarts(&self, id: &str) -> u32 {
+        self.containers
+            .lock()
+            .await
+            .get(id)
+            .map_or(0, |state| state.restarts)
+    }
+
+    /// Resolves with the first container exit that wasn't requested
+    pub async fn wait_for_unexpected_exit(&self) -> ContainerExit {
+        let mut rx = self.unexpected_exit.subscribe();
+        loop {
+            if let Some(exit) = rx.borrow_and_update().clone() {
+                return exit;
+            }
+            if rx.changed().await.is_err() {
+                return std::future::pending().await;
+            }
+        }
+    }
+
     fn extract_container_logs(
         docker: Docker,
         container_id: String,
         log_path: PathBuf,
+        stderr_path: PathBuf,
+        timestamps: bool,
         kind: &NodeKind,
     ) -> JoinHandle<Result<()>> {
         info!("{} stdout logs available at : {}", kind, log_path.display());
+        info!(
+            "{} stderr logs available at : {}",
+            kind,
+            stderr_path.display()
+        );
 
+        let kind = *kind;
         tokio::spawn(async move {
-            if let Some(parent) = log_path.parent() {
-                tokio::fs::create_dir_all(parent)
-                    .await
-                    .context("Failed to create log directory")?;
-            }
-            let mut log_file = File::create(log_path)
-                .await
-                .context("Failed to create log file")?;
-            let mut log_stream = docker.logs::<String>(
+            let mut log_file = create_log_file(kind, &log_path).await?;
+            let mut stderr_file = create_log_file(kind, &stderr_path).await?;
+
+            let mut log_stream = docker.logs(
                 &container_id,
                 Some(LogsOptions {
                     follow: true,
                     stdout: true,
                     stderr: true,
+                    timestamps,
                     ..Default::default()
                 }),
             );
 
             while let Some(Ok(log_output)) = log_stream.next().await {
-                let log_line = match log_output {
-                    LogOutput::Console { message } | LogOutput::StdOut { message } => message,
-                    _ => continue,
+                let (file, log_line) = match log_output {
+                    LogOutput::Console { message } | LogOutput::StdOut { message } => {
+                        (&mut log_file, message)
+                    }
+                    LogOutput::StdErr { message } => (&mut stderr_file, message),
+                    LogOutput::StdIn { .. } => continue,
                 };
-                log_file
-                    .write_all(&log_line)
+                file.write_all(&log_line)
                     .await
                     .context("Failed to write log line")?;
             }
@@ -342,13 +783,214 @@ impl DockerEnv {
         Ok(())
     }
 
-    // Should run bitcoin in docker
-    pub fn bitcoin(&self) -> bool {
-        self.test_case_config.bitcoin
+    /// Where the citrea image comes from
+    pub fn citrea_image(&self) -> &CitreaImageSource {
+        &self.test_case_config.citrea_image
+    }
+
+    // Should run node `kind` in docker
+    pub fn dockerized(&self, kind: NodeKind) -> bool {
+        self.test_case_config.is_dockerized(kind)
+    }
+}
+
+impl Drop for DockerEnv {
+    fn drop(&mut self) {
+        self.events_handle.abort();
+    }
+}
+
+/// Waits for any test container to exit unexpectedly and turns it into `Error::NodeExited`
+/// containing the container's last log lines.
+/// Never resolves when docker is not used.
+pub(crate) async fn wait_for_unexpected_exit(docker: &Option<DockerEnv>) -> anyhow::Error {
+    let Some(docker) = docker else {
+        return std::future::pending().await;
+    };
+
+    let exit = docker.wait_for_unexpected_exit().await;
+    Error::from(&exit).into()
+}
+
+impl From<&ContainerExit> for Error {
+    fn from(exit: &ContainerExit) -> Self {
+        let (tail_lines, stdout_tail, stderr_tail) = log_tails(&exit.log_path, &exit.stderr_path);
+        Error::NodeExited {
+            node: exit.kind,
+            id: format!("container {}", exit.id),
+            exit_code: exit.exit_code,
+            signal: None,
+            oom_killed: exit.oom_killed,
+            log_path: exit.log_path.clone(),
+            stderr_path: exit.stderr_path.clone(),
+            stdout_tail,
+            stderr_tail,
+            tail_lines,
+        }
+    }
+}
+
+/// Forwards the build context to the docker build request body
+struct BodyWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl io::Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Build request closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Matcher for the paths excluded from build context `context`.
+/// Follows `.dockerignore` when there is one, patterns being relative to the context root.
+/// Otherwise excludes `DEFAULT_BUILD_CONTEXT_EXCLUDES`.
+fn build_context_ignore(context: &Path) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(context);
+    let dockerignore = context.join(".dockerignore");
+
+    if dockerignore.exists() {
+        let content = fs::read_to_string(&dockerignore).map_err(Error::io(&dockerignore))?;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negation, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => ("!", pattern.trim()),
+                None => ("", line),
+            };
+            // Unlike gitignore, unanchored patterns only match from the context root
+            let pattern = pattern.trim_start_matches("./").trim_matches('/');
+            builder.add_line(Some(dockerignore.clone()), &format!("{negation}/{pattern}"))?;
+        }
+    } else {
+        for pattern in DEFAULT_BUILD_CONTEXT_EXCLUDES {
+            builder.add_line(None, pattern)?;
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Write build context `context` as a tar archive to `writer`, leaving out ignored paths.
+/// The Dockerfile and `.dockerignore` are always included, as docker does.
+fn archive_build_context(context: &Path, dockerfile: &str, writer: impl io::Write) -> Result<()> {
+    let ignore = build_context_ignore(context)?;
+    let always_included = [Path::new(dockerfile), Path::new(".dockerignore")];
+
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let path = context.join(&dir);
+        let mut entries = fs::read_dir(&path)
+            .map_err(Error::io(&path))?
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(Error::io(&path))?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+
+        for entry in entries {
+            let relative = dir.join(entry.file_name());
+            let is_dir = entry.file_type().map_err(Error::io(entry.path()))?.is_dir();
+            if ignore.matched(&relative, is_dir).is_ignore()
+                && !always_included.contains(&relative.as_path())
+            {
+                continue;
+            }
+
+            if is_dir {
+                builder.append_dir(&relative, entry.path())?;
+                dirs.push(relative);
+            } else {
+                builder.append_path_with_name(entry.path(), &relative)?;
+            }
+        }
     }
 
-    // Should run citrea in docker
-    pub fn citrea(&self) -> bool {
-        self.test_case_config.citrea
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+// Logs of previous containers of the node, i.e. before a restart, are kept
+async fn create_log_file(kind: NodeKind, path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(Error::node_io(kind, parent))?;
+    }
+    Ok(OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .await
+        .map_err(Error::node_io(kind, path))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archived_paths(context: &Path, dockerfile: &str) -> Vec<String> {
+        let mut tar = Vec::new();
+        archive_build_context(context, dockerfile, &mut tar).unwrap();
+        tar::Archive::new(tar.as_slice())
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_build_context() {
+        let context = tempfile::tempdir().unwrap();
+        for path in [
+            "Dockerfile",
+            "src/main.rs",
+            "src/debug.log",
+            "debug.log",
+            "target/bin",
+        ] {
+            let path = context.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let mut paths = archived_paths(context.path(), "Dockerfile");
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "Dockerfile",
+                "debug.log",
+                "src",
+                "src/debug.log",
+                "src/main.rs"
+            ]
+        );
+
+        fs::write(
+            context.path().join(".dockerignore"),
+            "# comment\n*.log\n!src\nDockerfile\n",
+        )
+        .unwrap();
+        let mut paths = archived_paths(context.path(), "Dockerfile");
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                ".dockerignore",
+                "Dockerfile",
+                "src",
+                "src/debug.log",
+                "src/main.rs",
+                "target",
+                "target/bin"
+            ]
+        );
     }
 }
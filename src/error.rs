@@ -0,0 +1,260 @@
+//! Typed framework failures.
+//!
+//! APIs keep returning `anyhow::Result`, framework failures being raised as an `Error` that
+//! tests can match on with `downcast_ref`, through any added context:
+//!
+//! ```ignore
+//! match e.downcast_ref::<citrea_e2e::Error>() {
+//!     Some(citrea_e2e::Error::Timeout { last_observed, .. }) => ...,
+//!     _ => ...,
+//! }
+//! ```
+
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+use crate::{config::ConfigErrors, node::NodeKind};
+
+/// Framework failure, the underlying error if any being its `source`
+#[derive(Debug)]
+pub enum Error {
+    /// A `Wait` condition didn't hold in time
+    Timeout {
+        node: Option<NodeKind>,
+        description: String,
+        timeout: Duration,
+        /// Last polled value, `Debug` formatted
+        last_observed: Option<String>,
+        /// Last retryable poll error
+        last_error: Option<String>,
+    },
+    /// A node process or container couldn't be started
+    SpawnFailed {
+        node: NodeKind,
+        source: anyhow::Error,
+    },
+    /// A node exited outside of a requested stop, crash or restart
+    NodeExited {
+        node: NodeKind,
+        /// i.e. "process 42" or "container <id>"
+        id: String,
+        exit_code: Option<i64>,
+        signal: Option<i32>,
+        oom_killed: bool,
+        log_path: PathBuf,
+        stderr_path: PathBuf,
+        /// Last stdout and stderr lines at the time of the exit
+        stdout_tail: String,
+        stderr_tail: String,
+        tail_lines: usize,
+    },
+    /// L2 node RPC call failure, `code` being set for JSON-RPC error responses
+    Rpc {
+        node: Option<NodeKind>,
+        method: String,
+        code: Option<i32>,
+        message: String,
+    },
+    Docker {
+        node: Option<NodeKind>,
+        source: bollard::errors::Error,
+    },
+    Config(ConfigErrors),
+    Io {
+        node: Option<NodeKind>,
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// Node the failure relates to, when known
+    pub fn node(&self) -> Option<NodeKind> {
+        match self {
+            Error::Timeout { node, .. }
+            | Error::Rpc { node, .. }
+            | Error::Docker { node, .. }
+            | Error::Io { node, .. } => *node,
+            Error::SpawnFailed { node, .. } | Error::NodeExited { node, .. } => Some(*node),
+            Error::Config(_) => None,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout { .. })
+    }
+
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Error::Io {
+            node: None,
+            path,
+            source,
+        }
+    }
+
+    /// I/O error on a file of `node`
+    pub(crate) fn node_io(
+        node: NodeKind,
+        path: impl Into<PathBuf>,
+    ) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Error::Io {
+            node: Some(node),
+            path,
+            source,
+        }
+    }
+
+    /// Docker request failure about the container of `node`
+    pub(crate) fn docker(node: NodeKind) -> impl FnOnce(bollard::errors::Error) -> Self {
+        move |source| Error::Docker {
+            node: Some(node),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Timeout {
+                node,
+                description,
+                timeout,
+                last_observed,
+                last_error,
+            } => {
+                write!(f, "Timeout after {timeout:?} waiting for ")?;
+                if let Some(node) = node {
+                    write!(f, "{node} ")?;
+                }
+                write!(f, "{description}")?;
+                match (last_error, last_observed) {
+                    (Some(e), _) => write!(f, ", last error: {e}"),
+                    (None, Some(value)) => write!(f, ", last observed {value}"),
+                    (None, None) => Ok(()),
+                }
+            }
+            Error::SpawnFailed { node, .. } => write!(f, "Failed to spawn {node}"),
+            Error::NodeExited {
+                node,
+                id,
+                exit_code,
+                signal,
+                oom_killed,
+                stdout_tail,
+                stderr_tail,
+                tail_lines,
+                ..
+            } => {
+                write!(f, "{node} {id} exited")?;
+                if let Some(exit_code) = exit_code {
+                    write!(f, " with exit code {exit_code}")?;
+                }
+                if let Some(signal) = signal {
+                    write!(f, " killed by signal {signal}")?;
+                }
+                if *oom_killed {
+                    write!(f, " (OOM killed)")?;
+                }
+                write!(
+                    f,
+                    " unexpectedly.\nLast {tail_lines} stdout lines:\n{stdout_tail}\nLast {tail_lines} stderr lines:\n{stderr_tail}"
+                )
+            }
+            Error::Rpc {
+                node,
+                method,
+                code,
+                message,
+            } => {
+                if let Some(node) = node {
+                    write!(f, "{node} ")?;
+                }
+                write!(f, "RPC {method} failed")?;
+                if let Some(code) = code {
+                    write!(f, " with code {code}")?;
+                }
+                write!(f, ": {message}")
+            }
+            Error::Docker { node, .. } => match node {
+                Some(node) => write!(f, "Docker request failed for {node}"),
+                None => write!(f, "Docker request failed"),
+            },
+            Error::Config(errors) => write!(f, "{errors}"),
+            Error::Io { node, path, .. } => {
+                write!(f, "I/O error on ")?;
+                if let Some(node) = node {
+                    write!(f, "{node} ")?;
+                }
+                write!(f, "{}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SpawnFailed { source, .. } => Some(source.as_ref()),
+            Error::Docker { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Timeout { .. }
+            | Error::NodeExited { .. }
+            | Error::Rpc { .. }
+            | Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<bollard::errors::Error> for Error {
+    fn from(source: bollard::errors::Error) -> Self {
+        Error::Docker { node: None, source }
+    }
+}
+
+impl From<ConfigErrors> for Error {
+    fn from(errors: ConfigErrors) -> Self {
+        Error::Config(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_downcast_through_context() {
+        let result: crate::Result<()> = Err(Error::Timeout {
+            node: Some(NodeKind::FullNode),
+            description: "L2 block 10".to_string(),
+            timeout: Duration::from_secs(1),
+            last_observed: Some("7".to_string()),
+            last_error: None,
+        })
+        .context("Full node didn't sync");
+
+        let e = result.unwrap_err();
+        let error = e.downcast_ref::<Error>().unwrap();
+        assert!(error.is_timeout());
+        assert_eq!(error.node(), Some(NodeKind::FullNode));
+        assert_eq!(
+            error.to_string(),
+            "Timeout after 1s waiting for full-node L2 block 10, last observed 7"
+        );
+
+        let error = Error::Rpc {
+            node: Some(NodeKind::Sequencer),
+            method: "ledger_getHeadL2BlockHeight".to_string(),
+            code: Some(-32601),
+            message: "Method not found".to_string(),
+        };
+        assert_eq!(error.node(), Some(NodeKind::Sequencer));
+        assert_eq!(
+            error.to_string(),
+            "sequencer RPC ledger_getHeadL2BlockHeight failed with code -32601: Method not found"
+        );
+    }
+}
@@ -1,43 +1,56 @@
 use std::{
+    fmt::Debug,
     future::Future,
     path::{Path, PathBuf},
     sync::{Arc, Once},
 };
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use bitcoincore_rpc::RpcApi;
-use tracing::{debug, info};
+use serde::Serialize;
+use tracing::{debug, info, warn};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 use crate::{
-    bitcoin::BitcoinNodeCluster,
+    bitcoin::{BitcoinNodeCluster, DEFAULT_FINALITY_DEPTH},
     citrea_cli::CitreaCli,
     config::{
-        BitcoinConfig, BitcoinServiceConfig, EmptyConfig, FullBatchProverConfig,
-        FullFullNodeConfig, FullLightClientProverConfig, FullSequencerConfig, RollupConfig,
-        RpcConfig, RunnerConfig, StorageConfig, TestCaseConfig, TestConfig,
+        merge_env, BitcoinConfig, BitcoinServiceConfig, DockerCompose, DockerConfig,
+        FullBatchProverConfig, FullFullNodeConfig, FullL2NodeConfig, FullLightClientProverConfig,
+        FullSequencerConfig, GenesisBuilder, GenesisPreset, RollupConfig, RpcConfig, RunnerConfig,
+        SequencerConfig, StorageConfig, TelemetryConfig, TestCaseConfig, TestCaseKeys, TestConfig,
+        PREVIOUS_CITREA_ENV,
     },
-    docker::DockerEnv,
+    docker::{DockerEnv, DOCKER_HOST_GATEWAY},
     log_provider::{LogPathProvider, LogPathProviderErased},
-    node::{BatchProver, FullNode, LightClientProver, NodeKind, Sequencer},
+    node::{BatchProver, FullNode, LightClientProver, Node, NodeKind, Readiness, Sequencer},
+    registry::{take_node, NodeErased, NodeStatus},
+    resources::ResourceSampler,
+    supervisor::Supervisor,
     test_case::{TestCase, CITREA_CLI_ENV},
-    traits::NodeT,
-    utils::{
-        copy_directory, get_available_port, get_default_genesis_path, get_workspace_root, tail_file,
-    },
+    traits::{NodeT, StopReport},
+    utils::{get_available_port, tail_file},
     Result,
 };
 
 pub struct TestContext {
     pub config: TestConfig,
     pub docker: Arc<Option<DockerEnv>>,
+    pub supervisor: Arc<Supervisor>,
+    pub resources: Arc<ResourceSampler>,
 }
 
 impl TestContext {
     fn new(config: TestConfig, docker: Option<DockerEnv>) -> Self {
+        let resources = ResourceSampler::new(
+            config.test_case.resource_sample_interval,
+            docker.as_ref().map(|docker| docker.docker.clone()),
+        );
         Self {
             config,
             docker: Arc::new(docker),
+            supervisor: Arc::new(Supervisor::new()),
+            resources: Arc::new(resources),
         }
     }
 }
@@ -49,8 +62,22 @@ pub struct TestFramework {
     pub batch_prover: Option<BatchProver>,
     pub light_client_prover: Option<LightClientProver>,
     pub full_node: Option<FullNode>,
+    /// Nodes spawned during the test, see `spawn_full_node`
+    pub spawned_full_nodes: Vec<FullNode>,
+    pub spawned_batch_provers: Vec<BatchProver>,
+    pub spawned_light_client_provers: Vec<LightClientProver>,
     pub initial_da_height: u64,
     pub citrea_cli: Option<CitreaCli>,
+    n_spawned: usize,
+}
+
+// Cleanup keeps going when a node fails to stop
+async fn stop_node(node: &mut dyn NodeErased) {
+    let kind = node.kind();
+    match node.stop().await {
+        Ok(report) => info!("Successfully stopped {kind}, {report}"),
+        Err(e) => warn!("Failed to stop {kind}: {e:?}"),
+    }
 }
 
 async fn create_optional<T>(pred: bool, f: impl Future<Output = Result<T>>) -> Result<Option<T>> {
@@ -64,6 +91,7 @@ async fn create_optional<T>(pred: bool, f: impl Future<Output = Result<T>>) -> R
 impl TestFramework {
     pub async fn new<T: TestCase>() -> Result<Self> {
         setup_logging();
+        T::check_config_hooks()?;
 
         let test_case = T::test_config();
         let docker = if test_case.docker.enabled() {
@@ -92,12 +120,94 @@ impl TestFramework {
             batch_prover: None,
             light_client_prover: None,
             full_node: None,
+            spawned_full_nodes: Vec::new(),
+            spawned_batch_provers: Vec::new(),
+            spawned_light_client_provers: Vec::new(),
             ctx,
             initial_da_height: 0,
             citrea_cli,
+            n_spawned: 0,
         })
     }
 
+    /// Keys the nodes were configured with
+    pub fn keys(&self) -> &TestCaseKeys {
+        &self.ctx.config.test_case.keys
+    }
+
+    pub(crate) fn docker(&self) -> Arc<Option<DockerEnv>> {
+        Arc::clone(&self.ctx.docker)
+    }
+
+    /// Previous citrea release to `Restart::upgrade` nodes from or to
+    pub fn previous_citrea_binary(&self) -> Result<PathBuf> {
+        self.ctx
+            .config
+            .test_case
+            .previous_citrea_binary
+            .clone()
+            .with_context(|| format!("{PREVIOUS_CITREA_ENV} is not set"))
+    }
+
+    /// Resource usage samples of the L2 nodes
+    pub fn resources(&self) -> Arc<ResourceSampler> {
+        Arc::clone(&self.ctx.resources)
+    }
+
+    /// Supervisor of the local citrea processes
+    pub fn supervisor(&self) -> Arc<Supervisor> {
+        Arc::clone(&self.ctx.supervisor)
+    }
+
+    /// Readiness of node `kind` at test start.
+    /// A full node has to sync to the sequencer head, provers to scan up to the finalized `initial_da_height`.
+    pub async fn readiness(&self, kind: NodeKind) -> Result<Readiness> {
+        // Provers only process finalized DA blocks
+        let finalized_da_height = self
+            .initial_da_height
+            .saturating_sub(DEFAULT_FINALITY_DEPTH)
+            + 1;
+        Ok(match kind {
+            NodeKind::FullNode => match &self.sequencer {
+                Some(sequencer) => {
+                    Readiness::L2Synced(sequencer.client.ledger_get_head_l2_block_height().await?)
+                }
+                None => Readiness::Reachable,
+            },
+            NodeKind::BatchProver => Readiness::L1Scanned(finalized_da_height),
+            NodeKind::LightClientProver => {
+                let prover = self
+                    .light_client_prover
+                    .as_ref()
+                    .context("Light client prover is not running")?;
+                // Nothing to scan before its start height is finalized
+                if prover.config.node.initial_da_height > finalized_da_height {
+                    Readiness::Reachable
+                } else {
+                    Readiness::L1Scanned(finalized_da_height)
+                }
+            }
+            NodeKind::Sequencer | NodeKind::Bitcoin => Readiness::Reachable,
+        })
+    }
+
+    /// Wait for the L2 nodes to be ready, in turn and within their `TestCaseConfig::ready_timeout`
+    pub async fn wait_for_nodes_ready(&self) -> Result<()> {
+        let test_case = &self.ctx.config.test_case;
+
+        // The sequencer comes first, full node readiness depending on its head
+        for node in self.nodes() {
+            if node.kind() == NodeKind::Bitcoin {
+                continue;
+            }
+            let kind = node.kind();
+            let readiness = self.readiness(kind).await?;
+            node.wait_until_ready(readiness, test_case.ready_timeout(kind))
+                .await?;
+        }
+        Ok(())
+    }
+
     pub async fn init_nodes(&mut self) -> Result<()> {
         // Use first node config for now, as citrea nodes are expected to interact only with this main node for now.
         // Additional bitcoin node are solely used for simulating a bitcoin network and tx propagation/re-orgs
@@ -110,6 +220,8 @@ impl TestFramework {
                 &self.ctx.config.sequencer,
                 bitcoin_config,
                 Arc::clone(&self.ctx.docker),
+                Arc::clone(&self.ctx.supervisor),
+                Arc::clone(&self.ctx.resources),
             ),
         )
         .await?;
@@ -120,7 +232,9 @@ impl TestFramework {
                 BatchProver::new(
                     &self.ctx.config.batch_prover,
                     bitcoin_config,
-                    Arc::clone(&self.ctx.docker)
+                    Arc::clone(&self.ctx.docker),
+                    Arc::clone(&self.ctx.supervisor),
+                    Arc::clone(&self.ctx.resources),
                 )
             ),
             create_optional(
@@ -128,7 +242,9 @@ impl TestFramework {
                 LightClientProver::new(
                     &self.ctx.config.light_client_prover,
                     bitcoin_config,
-                    Arc::clone(&self.ctx.docker)
+                    Arc::clone(&self.ctx.docker),
+                    Arc::clone(&self.ctx.supervisor),
+                    Arc::clone(&self.ctx.resources),
                 )
             ),
             create_optional(
@@ -136,7 +252,9 @@ impl TestFramework {
                 FullNode::new(
                     &self.ctx.config.full_node,
                     bitcoin_config,
-                    Arc::clone(&self.ctx.docker)
+                    Arc::clone(&self.ctx.docker),
+                    Arc::clone(&self.ctx.supervisor),
+                    Arc::clone(&self.ctx.resources),
                 )
             ),
         )?;
@@ -144,33 +262,181 @@ impl TestFramework {
         Ok(())
     }
 
+    /// Config for an additional full node, derived from the test full node config.
+    /// Ports, dir, storage and DA tx backup dir are freshly allocated so that the node syncs from scratch.
+    pub fn new_full_node_config(&mut self) -> Result<FullFullNodeConfig> {
+        let template = self.ctx.config.full_node.clone();
+        self.derive_node_config(template)
+    }
+
+    /// Config for an additional batch prover, see `new_full_node_config`.
+    /// It shares the DA wallet and key of the test batch prover, so only one of them can run at a time.
+    pub fn new_batch_prover_config(&mut self) -> Result<FullBatchProverConfig> {
+        let template = self.ctx.config.batch_prover.clone();
+        self.derive_node_config(template)
+    }
+
+    /// Config for an additional light client prover, see `new_full_node_config`
+    pub fn new_light_client_prover_config(&mut self) -> Result<FullLightClientProverConfig> {
+        let template = self.ctx.config.light_client_prover.clone();
+        self.derive_node_config(template)
+    }
+
+    fn derive_node_config<C>(
+        &mut self,
+        template: FullL2NodeConfig<C>,
+    ) -> Result<FullL2NodeConfig<C>>
+    where
+        C: Clone + Debug + Serialize + Send + Sync,
+    {
+        self.n_spawned += 1;
+        // Distinct from the `{kind}-{INDEX}` restart dirs
+        let name = format!("{}-spawned-{}", template.kind(), self.n_spawned);
+        let test_dir = &self.ctx.config.test_case.dir;
+        let dir = test_dir.join(&name);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {} directory", dir.display()))?;
+
+        let tx_backup_dir = dir.join("tx_backup");
+        std::fs::create_dir_all(&tx_backup_dir)
+            .with_context(|| format!("Failed to create {} directory", tx_backup_dir.display()))?;
+
+        let mut config = template;
+        config.rollup.da.tx_backup_dir = tx_backup_dir.display().to_string();
+        config.set_dir(dir);
+        config.rollup.rpc.bind_port = get_available_port()?;
+        config.rollup.telemetry.bind_port = get_available_port()?;
+        config.rollup.storage.path = test_dir.join("dbs").join(format!("{name}-db"));
+        config.write_configs()?;
+        Ok(config)
+    }
+
+    /// Spawn an additional full node, i.e. joining late, tracked for log dumps and cleanup.
+    /// See `new_full_node_config` to get a config.
+    pub async fn spawn_full_node(&mut self, config: FullFullNodeConfig) -> Result<&mut FullNode> {
+        let node = self.spawn_node(config).await?;
+        self.spawned_full_nodes.push(node);
+        Ok(self.spawned_full_nodes.last_mut().unwrap())
+    }
+
+    /// Spawn an additional batch prover, see `spawn_full_node`.
+    /// Fails while another batch prover is running, as they would spend from the same DA wallet.
+    pub async fn spawn_batch_prover(
+        &mut self,
+        config: FullBatchProverConfig,
+    ) -> Result<&mut BatchProver> {
+        for prover in self.batch_prover.iter().chain(&self.spawned_batch_provers) {
+            if prover.status().await? == NodeStatus::Running {
+                bail!("Cannot spawn a batch prover while another one is running, stop it first");
+            }
+        }
+        let node = self.spawn_node(config).await?;
+        self.spawned_batch_provers.push(node);
+        Ok(self.spawned_batch_provers.last_mut().unwrap())
+    }
+
+    /// Spawn an additional light client prover, see `spawn_full_node`
+    pub async fn spawn_light_client_prover(
+        &mut self,
+        config: FullLightClientProverConfig,
+    ) -> Result<&mut LightClientProver> {
+        let node = self.spawn_node(config).await?;
+        self.spawned_light_client_provers.push(node);
+        Ok(self.spawned_light_client_provers.last_mut().unwrap())
+    }
+
+    /// Stop and remove full node `index`, indexed as by `node`, later nodes being shifted down
+    pub async fn remove_full_node(&mut self, index: usize) -> Result<StopReport> {
+        let mut node = take_node(&mut self.full_node, &mut self.spawned_full_nodes, index)
+            .with_context(|| format!("No full node {index}"))?;
+        NodeT::stop(&mut node).await
+    }
+
+    /// Stop and remove batch prover `index`, see `remove_full_node`
+    pub async fn remove_batch_prover(&mut self, index: usize) -> Result<StopReport> {
+        let mut node = take_node(
+            &mut self.batch_prover,
+            &mut self.spawned_batch_provers,
+            index,
+        )
+        .with_context(|| format!("No batch prover {index}"))?;
+        NodeT::stop(&mut node).await
+    }
+
+    /// Stop and remove light client prover `index`, see `remove_full_node`
+    pub async fn remove_light_client_prover(&mut self, index: usize) -> Result<StopReport> {
+        let mut node = take_node(
+            &mut self.light_client_prover,
+            &mut self.spawned_light_client_provers,
+            index,
+        )
+        .with_context(|| format!("No light client prover {index}"))?;
+        NodeT::stop(&mut node).await
+    }
+
+    // Spawned containers are named after the node dir, with their own volume, see `DockerConfig::name`.
+    // Full nodes have to sync to the sequencer head, other nodes only to be reachable.
+    async fn spawn_node<C>(&self, config: FullL2NodeConfig<C>) -> Result<Node<C>>
+    where
+        C: Clone + Debug + Serialize + Send + Sync,
+        DockerConfig: From<FullL2NodeConfig<C>>,
+    {
+        let kind = config.kind();
+        let node = Node::new(
+            &config,
+            &self.ctx.config.bitcoin[0],
+            Arc::clone(&self.ctx.docker),
+            Arc::clone(&self.ctx.supervisor),
+            Arc::clone(&self.ctx.resources),
+        )
+        .await?;
+        let readiness = match kind {
+            NodeKind::FullNode => self.readiness(kind).await?,
+            _ => Readiness::Reachable,
+        };
+        node.wait_until_ready(readiness, self.ctx.config.test_case.ready_timeout(kind))
+            .await?;
+        Ok(node)
+    }
+
+    // Nodes started with the test are listed from their config, to get the logs of a failed start
     fn get_nodes_as_log_provider(&self) -> Vec<&dyn LogPathProviderErased> {
-        let test_case = &self.ctx.config.test_case;
+        let config = &self.ctx.config;
+        let test_case = &config.test_case;
 
-        self.ctx
-            .config
+        config
             .bitcoin
             .iter()
             .map(LogPathProvider::as_erased)
-            .map(Option::Some)
-            .chain(vec![
-                test_case
-                    .with_sequencer
-                    .then(|| LogPathProvider::as_erased(&self.ctx.config.sequencer)),
-                test_case
-                    .with_full_node
-                    .then(|| LogPathProvider::as_erased(&self.ctx.config.full_node)),
-                test_case
-                    .with_batch_prover
-                    .then(|| LogPathProvider::as_erased(&self.ctx.config.batch_prover)),
-                test_case
-                    .with_light_client_prover
-                    .then(|| LogPathProvider::as_erased(&self.ctx.config.light_client_prover)),
-            ])
-            .flatten()
+            .chain(
+                [
+                    (test_case.with_sequencer, config.sequencer.as_erased()),
+                    (test_case.with_batch_prover, config.batch_prover.as_erased()),
+                    (
+                        test_case.with_light_client_prover,
+                        config.light_client_prover.as_erased(),
+                    ),
+                    (test_case.with_full_node, config.full_node.as_erased()),
+                ]
+                .into_iter()
+                .filter_map(|(enabled, provider)| enabled.then_some(provider)),
+            )
+            .chain(
+                self.spawned_nodes()
+                    .into_iter()
+                    .map(|node| node.log_provider()),
+            )
             .collect()
     }
 
+    /// Write a docker-compose file reproducing the test environment into the test dir.
+    /// Returns the compose file path.
+    pub fn export_docker_compose(&self) -> Result<PathBuf> {
+        let path = self.ctx.config.docker_compose_path();
+        DockerCompose::from_test_config(&self.ctx.config)?.write(&path)?;
+        Ok(path)
+    }
+
     pub fn dump_logs(&self) -> Result<()> {
         debug!("Dumping logs:");
 
@@ -217,29 +483,20 @@ impl TestFramework {
     pub async fn stop(&mut self) -> Result<()> {
         info!("Stopping framework...");
 
-        if let Some(sequencer) = &mut self.sequencer {
-            let _ = sequencer.stop().await;
-            info!("Successfully stopped sequencer");
-        }
-
-        if let Some(batch_prover) = &mut self.batch_prover {
-            let _ = batch_prover.stop().await;
-            info!("Successfully stopped batch_prover");
-        }
-
-        if let Some(light_client_prover) = &mut self.light_client_prover {
-            let _ = light_client_prover.stop().await;
-            info!("Successfully stopped light_client_prover");
+        if let Err(e) = self
+            .ctx
+            .resources
+            .write_report(&self.ctx.config.test_case.dir)
+            .await
+        {
+            warn!("Failed to write resource report: {e:?}");
         }
 
-        if let Some(full_node) = &mut self.full_node {
-            let _ = full_node.stop().await;
-            info!("Successfully stopped full_node");
+        // Bitcoin nodes come last
+        for node in self.nodes_mut() {
+            stop_node(node).await;
         }
 
-        let _ = self.bitcoin_nodes.stop_all().await;
-        info!("Successfully stopped bitcoin nodes");
-
         if let Some(docker) = self.ctx.docker.as_ref() {
             let _ = docker.cleanup().await;
             info!("Successfully cleaned docker");
@@ -268,8 +525,8 @@ impl TestFramework {
 
         let da = self.bitcoin_nodes.get(0).unwrap();
 
-        let blocks_to_mature = 100;
-        let blocks_to_fund = 25;
+        let blocks_to_mature = DA_BLOCKS_TO_MATURE;
+        let blocks_to_fund = DA_BLOCKS_TO_FUND;
         if self.ctx.config.test_case.with_sequencer {
             da.fund_wallet(NodeKind::Sequencer.to_string(), blocks_to_fund)
                 .await?;
@@ -294,8 +551,27 @@ impl TestFramework {
     }
 }
 
+// Blocks mined by `fund_da_wallets`, per funded wallet and to mature coinbase outputs
+const DA_BLOCKS_TO_FUND: u64 = 25;
+const DA_BLOCKS_TO_MATURE: u64 = 100;
+
+/// Height of the DA chain once wallets are funded, i.e. when citrea nodes are spawned
+pub(crate) fn expected_initial_da_height(test_case: &TestCaseConfig) -> u64 {
+    let funded_wallets = [
+        test_case.with_sequencer,
+        test_case.with_batch_prover,
+        test_case.with_light_client_prover,
+        // Default bitcoin wallet
+        true,
+    ]
+    .into_iter()
+    .filter(|funded| *funded)
+    .count() as u64;
+    funded_wallets * DA_BLOCKS_TO_FUND + DA_BLOCKS_TO_MATURE
+}
+
 fn generate_test_config<T: TestCase>(
-    test_case: TestCaseConfig,
+    mut test_case: TestCaseConfig,
     docker: &Option<DockerEnv>,
 ) -> Result<TestConfig> {
     let env = T::test_env();
@@ -303,6 +579,15 @@ fn generate_test_config<T: TestCase>(
     let batch_prover = T::batch_prover_config();
     let light_client_prover = T::light_client_prover_config();
     let sequencer = T::sequencer_config();
+    test_case.keys = test_case
+        .keys
+        .clone()
+        .with_configured_sequencer_key(&sequencer.private_key)?;
+    let keys = &test_case.keys;
+    let sequencer = SequencerConfig {
+        private_key: keys.sequencer_private_key(),
+        ..sequencer
+    };
     let sequencer_rollup = RollupConfig::default();
     let batch_prover_rollup = RollupConfig::default();
     let light_client_prover_rollup = RollupConfig::default();
@@ -312,7 +597,10 @@ fn generate_test_config<T: TestCase>(
     let [bitcoin_dir, dbs_dir, batch_prover_dir, light_client_prover_dir, sequencer_dir, full_node_dir, genesis_dir, tx_backup_dir] =
         create_dirs(&test_case.dir)?;
 
-    copy_genesis_dir(&test_case.genesis_dir, &genesis_dir)?;
+    match &test_case.genesis {
+        Some(genesis) => genesis.write(&genesis_dir)?,
+        None => write_genesis_dir(&test_case.genesis_dir, &genesis_dir)?,
+    }
 
     let mut bitcoin_confs = vec![];
     for i in 0..test_case.n_nodes {
@@ -327,27 +615,67 @@ fn generate_test_config<T: TestCase>(
             p2p_port,
             rpc_port,
             data_dir,
-            env: env.bitcoin().clone(),
+            env: merge_env([env.bitcoin(), bitcoin.env.clone()]),
             idx: i,
             ..bitcoin.clone()
         });
     }
 
-    bitcoin_confs[0].docker_host = docker
-        .as_ref()
-        .and_then(|d| d.citrea().then(|| d.get_hostname(&NodeKind::Bitcoin)));
-
-    // Target first bitcoin node as DA for now
-    let da_config: BitcoinServiceConfig = bitcoin_confs[0].clone().into();
+    let dockerized = |kind: NodeKind| docker.as_ref().is_some_and(|d| d.dockerized(kind));
+    let any_l2_dockerized = [
+        NodeKind::Sequencer,
+        NodeKind::BatchProver,
+        NodeKind::LightClientProver,
+        NodeKind::FullNode,
+    ]
+    .into_iter()
+    .any(dockerized);
 
-    let runner_bind_host = match docker.as_ref() {
-        Some(d) if d.citrea() => d.get_hostname(&NodeKind::Sequencer),
+    // Host under which node `to` is reachable from node `from`
+    let host = |from: NodeKind, to: NodeKind| match docker.as_ref() {
+        Some(d) if dockerized(from) && dockerized(to) => d.get_hostname(&to),
+        _ if dockerized(from) => DOCKER_HOST_GATEWAY.to_string(),
         _ => sequencer_rollup.rpc.bind_host.clone(),
     };
 
-    let bind_host = match docker.as_ref() {
-        Some(d) if d.citrea() => "0.0.0.0".to_string(),
-        _ => sequencer_rollup.rpc.bind_host.clone(),
+    // Containers listen on every interface.
+    // A local sequencer also has to when it is reached by containerized nodes
+    let bind_host = |kind: NodeKind| {
+        if dockerized(kind) || (kind == NodeKind::Sequencer && any_l2_dockerized) {
+            "0.0.0.0".to_string()
+        } else {
+            sequencer_rollup.rpc.bind_host.clone()
+        }
+    };
+
+    let subnet = docker.as_ref().map(|d| d.network_info.subnet.clone());
+    if dockerized(NodeKind::Bitcoin) {
+        for conf in bitcoin_confs.iter_mut() {
+            conf.docker_subnet.clone_from(&subnet);
+        }
+        if any_l2_dockerized {
+            bitcoin_confs[0].docker_host =
+                docker.as_ref().map(|d| d.get_hostname(&NodeKind::Bitcoin));
+        }
+    } else if let (true, Some(subnet)) = (any_l2_dockerized, subnet) {
+        // Allow containers of the test network to reach local bitcoind RPC
+        bitcoin_confs[0].extra_args.extend([
+            "-rpcbind=0.0.0.0".to_string(),
+            "-rpcallowip=127.0.0.1".to_string(),
+            format!("-rpcallowip={subnet}"),
+        ]);
+    }
+
+    // Target first bitcoin node as DA for now
+    let da_config: BitcoinServiceConfig = bitcoin_confs[0].clone().into();
+    let da_rpc_port = bitcoin_confs[0].rpc_port;
+    let da_node_url = |kind: NodeKind, wallet: &str| {
+        format!(
+            "http://{}:{}/wallet/{}",
+            host(kind, NodeKind::Bitcoin),
+            da_rpc_port,
+            wallet
+        )
     };
 
     let sequencer_rollup = {
@@ -355,10 +683,8 @@ fn generate_test_config<T: TestCase>(
         let node_kind = NodeKind::Sequencer.to_string();
         RollupConfig {
             da: BitcoinServiceConfig {
-                da_private_key: Some(
-                    "E9873D79C6D87DC0FB6A5778633389F4453213303DA61F20BD67FC233AA33262".to_string(),
-                ),
-                node_url: format!("http://{}/wallet/{}", da_config.node_url, node_kind),
+                da_private_key: Some(keys.sequencer_da_private_key()),
+                node_url: da_node_url(NodeKind::Sequencer, &node_kind),
                 tx_backup_dir: tx_backup_dir.display().to_string(),
                 ..da_config.clone()
             },
@@ -368,33 +694,40 @@ fn generate_test_config<T: TestCase>(
             },
             rpc: RpcConfig {
                 bind_port,
-                bind_host: bind_host.clone(),
+                bind_host: bind_host(NodeKind::Sequencer),
                 ..sequencer_rollup.rpc
             },
+            public_keys: keys.node_public_keys(NodeKind::Sequencer),
+            telemetry: TelemetryConfig {
+                bind_host: bind_host(NodeKind::Sequencer),
+                bind_port: get_available_port()?,
+            },
             ..sequencer_rollup
         }
     };
-
-    let runner_config = Some(RunnerConfig {
-        sequencer_client_url: format!(
-            "http://{}:{}",
-            runner_bind_host, sequencer_rollup.rpc.bind_port,
-        ),
-        include_tx_body: true,
-        sync_blocks_count: 10,
-        pruning_config: None,
-        scan_l1_start_height,
-    });
+    let sequencer_rollup = T::rollup_config(NodeKind::Sequencer, sequencer_rollup);
+
+    let runner_config = |kind: NodeKind| {
+        Some(RunnerConfig {
+            sequencer_client_url: format!(
+                "http://{}:{}",
+                host(kind, NodeKind::Sequencer),
+                sequencer_rollup.rpc.bind_port,
+            ),
+            include_tx_body: true,
+            sync_blocks_count: 10,
+            pruning_config: T::pruning_config(kind),
+            scan_l1_start_height,
+        })
+    };
 
     let batch_prover_rollup = {
         let bind_port = get_available_port()?;
         let node_kind = NodeKind::BatchProver.to_string();
         RollupConfig {
             da: BitcoinServiceConfig {
-                da_private_key: Some(
-                    "56D08C2DDE7F412F80EC99A0A328F76688C904BD4D1435281EFC9270EC8C8707".to_string(),
-                ),
-                node_url: format!("http://{}/wallet/{}", da_config.node_url, node_kind),
+                da_private_key: Some(keys.prover_da_private_key()),
+                node_url: da_node_url(NodeKind::BatchProver, &node_kind),
                 tx_backup_dir: tx_backup_dir.display().to_string(),
                 ..da_config.clone()
             },
@@ -404,13 +737,18 @@ fn generate_test_config<T: TestCase>(
             },
             rpc: RpcConfig {
                 bind_port,
-                bind_host: bind_host.clone(),
+                bind_host: bind_host(NodeKind::BatchProver),
                 ..batch_prover_rollup.rpc
             },
-            runner: runner_config.clone(),
-            ..batch_prover_rollup
+            public_keys: keys.node_public_keys(NodeKind::BatchProver),
+            telemetry: TelemetryConfig {
+                bind_host: bind_host(NodeKind::BatchProver),
+                bind_port: get_available_port()?,
+            },
+            runner: runner_config(NodeKind::BatchProver),
         }
     };
+    let batch_prover_rollup = T::rollup_config(NodeKind::BatchProver, batch_prover_rollup);
 
     let light_client_prover_rollup = {
         let bind_port = get_available_port()?;
@@ -418,7 +756,7 @@ fn generate_test_config<T: TestCase>(
         RollupConfig {
             da: BitcoinServiceConfig {
                 da_private_key: None,
-                node_url: format!("http://{}/wallet/{}", da_config.node_url, node_kind),
+                node_url: da_node_url(NodeKind::LightClientProver, &node_kind),
                 tx_backup_dir: tx_backup_dir.display().to_string(),
                 ..da_config.clone()
             },
@@ -428,24 +766,27 @@ fn generate_test_config<T: TestCase>(
             },
             rpc: RpcConfig {
                 bind_port,
-                bind_host: bind_host.clone(),
+                bind_host: bind_host(NodeKind::LightClientProver),
                 ..light_client_prover_rollup.rpc
             },
-            runner: runner_config.clone(),
-            ..light_client_prover_rollup
+            public_keys: keys.node_public_keys(NodeKind::LightClientProver),
+            telemetry: TelemetryConfig {
+                bind_host: bind_host(NodeKind::LightClientProver),
+                bind_port: get_available_port()?,
+            },
+            runner: runner_config(NodeKind::LightClientProver),
         }
     };
+    let light_client_prover_rollup =
+        T::rollup_config(NodeKind::LightClientProver, light_client_prover_rollup);
 
     let full_node_rollup = {
         let bind_port = get_available_port()?;
         let node_kind = NodeKind::FullNode.to_string();
         RollupConfig {
             da: BitcoinServiceConfig {
-                node_url: format!(
-                    "http://{}/wallet/{}",
-                    da_config.node_url,
-                    NodeKind::Bitcoin // Use default wallet
-                ),
+                // Use default wallet
+                node_url: da_node_url(NodeKind::FullNode, &NodeKind::Bitcoin.to_string()),
                 tx_backup_dir: tx_backup_dir.display().to_string(),
                 ..da_config.clone()
             },
@@ -455,16 +796,21 @@ fn generate_test_config<T: TestCase>(
             },
             rpc: RpcConfig {
                 bind_port,
-                bind_host: bind_host.clone(),
+                bind_host: bind_host(NodeKind::FullNode),
                 ..full_node_rollup.rpc
             },
-            runner: runner_config.clone(),
-            ..full_node_rollup
+            public_keys: keys.node_public_keys(NodeKind::FullNode),
+            telemetry: TelemetryConfig {
+                bind_host: bind_host(NodeKind::FullNode),
+                bind_port: get_available_port()?,
+            },
+            runner: runner_config(NodeKind::FullNode),
         }
     };
+    let full_node_rollup = T::rollup_config(NodeKind::FullNode, full_node_rollup);
 
     let citrea_docker_image = std::env::var("CITREA_DOCKER_IMAGE").ok();
-    Ok(TestConfig {
+    let mut config = TestConfig {
         bitcoin: bitcoin_confs,
         sequencer: FullSequencerConfig::new(
             NodeKind::Sequencer,
@@ -473,8 +819,10 @@ fn generate_test_config<T: TestCase>(
             citrea_docker_image.clone(),
             sequencer_dir,
             env.sequencer(),
-            test_case.mode,
-        )?,
+            test_case.mode.clone(),
+        )?
+        .with_config_version(test_case.citrea_config_version.as_deref())?
+        .with_stop_timeout(test_case.stop_timeout),
         batch_prover: FullBatchProverConfig::new(
             NodeKind::BatchProver,
             batch_prover,
@@ -482,8 +830,10 @@ fn generate_test_config<T: TestCase>(
             citrea_docker_image.clone(),
             batch_prover_dir,
             env.batch_prover(),
-            test_case.mode,
-        )?,
+            test_case.mode.clone(),
+        )?
+        .with_config_version(test_case.citrea_config_version.as_deref())?
+        .with_stop_timeout(test_case.stop_timeout),
         light_client_prover: FullLightClientProverConfig::new(
             NodeKind::LightClientProver,
             light_client_prover,
@@ -491,19 +841,32 @@ fn generate_test_config<T: TestCase>(
             citrea_docker_image.clone(),
             light_client_prover_dir,
             env.light_client_prover(),
-            test_case.mode,
-        )?,
+            test_case.mode.clone(),
+        )?
+        .with_config_version(test_case.citrea_config_version.as_deref())?
+        .with_stop_timeout(test_case.stop_timeout),
         full_node: FullFullNodeConfig::new(
             NodeKind::FullNode,
-            EmptyConfig,
+            T::full_node_config(),
             full_node_rollup,
             citrea_docker_image,
             full_node_dir,
             env.full_node(),
-            test_case.mode,
-        )?,
+            test_case.mode.clone(),
+        )?
+        .with_config_version(test_case.citrea_config_version.as_deref())?
+        .with_stop_timeout(test_case.stop_timeout),
         test_case,
-    })
+    };
+
+    let binaries = &config.test_case.citrea_binaries;
+    config.sequencer.base.binary = binaries.get(&NodeKind::Sequencer).cloned();
+    config.batch_prover.base.binary = binaries.get(&NodeKind::BatchProver).cloned();
+    config.light_client_prover.base.binary = binaries.get(&NodeKind::LightClientProver).cloned();
+    config.full_node.base.binary = binaries.get(&NodeKind::FullNode).cloned();
+    config.validate()?;
+
+    Ok(config)
 }
 
 fn create_dirs(base_dir: &Path) -> Result<[PathBuf; 8]> {
@@ -527,20 +890,14 @@ fn create_dirs(base_dir: &Path) -> Result<[PathBuf; 8]> {
     Ok(paths)
 }
 
-fn copy_genesis_dir(genesis_dir: &Option<String>, target_dir: &Path) -> std::io::Result<()> {
-    let genesis_dir =
-        genesis_dir
-            .as_ref()
-            .map(PathBuf::from)
-            .map_or_else(get_default_genesis_path, |dir| {
-                if dir.is_absolute() {
-                    dir
-                } else {
-                    get_workspace_root().join(dir)
-                }
-            });
+fn write_genesis_dir(genesis_dir: &Option<String>, target_dir: &Path) -> Result<()> {
+    let preset = genesis_dir
+        .as_ref()
+        .map_or_else(GenesisPreset::default, |dir| {
+            GenesisPreset::Dir(PathBuf::from(dir))
+        });
 
-    copy_directory(genesis_dir, target_dir)
+    GenesisBuilder::from_preset(preset)?.write(target_dir)
 }
 
 static INIT: Once = Once::new();
@@ -4,13 +4,22 @@ mod citrea_config;
 mod client;
 pub mod config;
 mod docker;
+mod error;
 pub mod framework;
 mod log_provider;
+pub mod metrics;
 pub mod node;
+mod pruning;
+pub mod registry;
+pub mod resources;
 mod sequencer;
+pub mod supervisor;
 pub mod test_case;
 pub mod traits;
 mod utils;
+pub mod wait;
+
+pub use error::Error;
 
 pub type Result<T> = anyhow::Result<T>;
 
@@ -0,0 +1,244 @@
+//! Prometheus scraper for the telemetry endpoint of L2 nodes.
+
+use std::{collections::BTreeMap, fmt::Debug, time::Duration};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{node::Node, wait::Wait, Result};
+
+/// Sample of a Prometheus text-format metric
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricSample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub samples: Vec<MetricSample>,
+}
+
+impl Metrics {
+    /// Parse Prometheus text exposition format, comments and invalid lines are skipped
+    pub fn parse(text: &str) -> Self {
+        let samples = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(parse_sample)
+            .collect();
+        Self { samples }
+    }
+
+    /// Sum of the samples named `name` having all `labels`, `None` if there is none
+    pub fn value(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        self.samples
+            .iter()
+            .filter(|sample| {
+                sample.name == name
+                    && labels
+                        .iter()
+                        .all(|(k, v)| sample.labels.get(*k).is_some_and(|value| value == v))
+            })
+            .map(|sample| sample.value)
+            .reduce(|a, b| a + b)
+    }
+}
+
+// Parse `name{label="value",...} value [timestamp]`
+fn parse_sample(line: &str) -> Option<MetricSample> {
+    let (name, labels, rest) = match line.find('{') {
+        Some(start) => {
+            let end = start + line[start..].find('}')?;
+            (
+                &line[..start],
+                parse_labels(&line[start + 1..end])?,
+                &line[end + 1..],
+            )
+        }
+        None => {
+            let (name, rest) = line.split_once(char::is_whitespace)?;
+            (name, BTreeMap::new(), rest)
+        }
+    };
+    let value = rest.split_whitespace().next()?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        value => value.parse().ok()?,
+    };
+
+    Some(MetricSample {
+        name: name.trim().to_string(),
+        labels,
+        value,
+    })
+}
+
+fn parse_labels(labels: &str) -> Option<BTreeMap<String, String>> {
+    let mut parsed = BTreeMap::new();
+    let mut rest = labels.trim();
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let value = value.trim_start().strip_prefix('"')?;
+
+        // Find closing quote, skipping escaped ones
+        let mut escaped = false;
+        let end = value.char_indices().find_map(|(i, c)| match c {
+            '\\' if !escaped => {
+                escaped = true;
+                None
+            }
+            '"' if !escaped => Some(i),
+            _ => {
+                escaped = false;
+                None
+            }
+        })?;
+
+        parsed.insert(key.trim().to_string(), unescape(&value[..end]));
+        rest = value[end + 1..].trim_start().trim_start_matches(',').trim();
+    }
+    Some(parsed)
+}
+
+// Unescape `\\`, `\"` and `\n` in a single pass, other escapes being kept as is
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(c @ ('\\' | '"')) => unescaped.push(c),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+impl<C> Node<C>
+where
+    C: Clone + Debug + Serialize + Send + Sync,
+{
+    /// Scrape the node telemetry endpoint
+    pub async fn metrics(&self) -> Result<Metrics> {
+        let telemetry = &self.config.rollup.telemetry;
+        // Nodes listening on every interface, i.e. in docker, are reached through loopback
+        let host = match telemetry.bind_host.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            host => host,
+        };
+        let address = format!("{host}:{}", telemetry.bind_port);
+        let mut stream = TcpStream::connect(&address).await.with_context(|| {
+            format!(
+                "Failed to connect to {} telemetry at {address}",
+                self.config.kind()
+            )
+        })?;
+
+        // HTTP/1.0 so that the body is neither chunked nor kept alive
+        let request = format!("GET /metrics HTTP/1.0\r\nHost: {address}\r\n\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .context("Invalid metrics response")?;
+        let status = head.lines().next().unwrap_or_default();
+        if !status.contains(" 200") {
+            bail!("Failed to scrape {} metrics: {status}", self.config.kind());
+        }
+
+        Ok(Metrics::parse(body))
+    }
+
+    /// Current value of metric `name` with `labels`, see `Metrics::value`
+    pub async fn metric_value(&self, name: &str, labels: &[(&str, &str)]) -> Result<Option<f64>> {
+        Ok(self.metrics().await?.value(name, labels))
+    }
+
+    /// Wait for metric `name` with `labels` to reach at least `value`
+    pub async fn wait_for_metric(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+        timeout: Option<Duration>,
+    ) -> Result<f64> {
+        let current = Wait::new(format!("metric {name}{labels:?} to reach {value}"))
+            .with_node(self.config.kind())
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(30)))
+            .with_interval(Duration::from_millis(500))
+            .until(
+                || async { Ok(self.metric_value(name, labels).await.ok().flatten()) },
+                |current| current.is_some_and(|current| current >= value),
+            )
+            .await?;
+        Ok(current.expect("Checked by the wait condition"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metrics() {
+        let metrics = Metrics::parse(
+            r#"
+            # HELP sequencer_commitments Number of commitments sent
+            # TYPE sequencer_commitments counter
+            sequencer_commitments 3
+            proofs_total{kind="batch",status="ok"} 2 1700000000000
+            proofs_total{kind="batch",status="failed"} 1
+            proofs_total{kind="light client",status="ok"} 4
+            l1_block_process_duration_bucket{le="+Inf"} 7
+            escaped{path="a\"b\\c"} 1e3
+            newline{path="a\\nb",help="c\nd"} 1
+            "#,
+        );
+
+        assert_eq!(metrics.samples.len(), 7);
+        assert_eq!(metrics.value("sequencer_commitments", &[]), Some(3.0));
+        assert_eq!(metrics.value("proofs_total", &[]), Some(7.0));
+        assert_eq!(
+            metrics.value("proofs_total", &[("kind", "batch")]),
+            Some(3.0)
+        );
+        assert_eq!(
+            metrics.value(
+                "proofs_total",
+                &[("kind", "light client"), ("status", "ok")]
+            ),
+            Some(4.0)
+        );
+        assert_eq!(
+            metrics.value("l1_block_process_duration_bucket", &[("le", "+Inf")]),
+            Some(7.0)
+        );
+        assert_eq!(
+            metrics.value("escaped", &[("path", "a\"b\\c")]),
+            Some(1000.0)
+        );
+        assert_eq!(
+            metrics.value("newline", &[("path", "a\\nb"), ("help", "c\nd")]),
+            Some(1.0)
+        );
+        assert_eq!(metrics.value("missing", &[]), None);
+    }
+}
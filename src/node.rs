@@ -1,36 +1,43 @@
 use std::{
     fmt::{self, Debug},
-    fs::File,
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
     process::Stdio,
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
     },
-    time::{Duration, SystemTime},
+    time::Duration,
 };
 
-use anyhow::{anyhow, bail, Context};
+use anyhow::{bail, Context};
 use async_trait::async_trait;
 use bitcoincore_rpc::{Auth, Client as BitcoinClient};
-use serde::Serialize;
-use tokio::{
-    process::Command,
-    time::{sleep, Instant},
+use bollard::{
+    query_parameters::{KillContainerOptions, WaitContainerOptions},
+    Docker,
 };
-use tracing::{debug, info, trace};
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::{process::Command, time::Instant};
+use tracing::{debug, info};
 
 pub use crate::sequencer::Sequencer;
 use crate::{
     client::Client,
     config::{
-        BatchProverConfig, BitcoinConfig, DockerConfig, EmptyConfig, FullL2NodeConfig,
-        LightClientProverConfig,
+        BatchProverConfig, BitcoinConfig, CitreaImageSource, DockerConfig, EmptyConfig,
+        FullL2NodeConfig, LightClientProverConfig,
     },
-    docker::DockerEnv,
+    docker::{ContainerSpawnOutput, DockerEnv},
     log_provider::LogPathProvider,
-    traits::{NodeT, Restart, SpawnOutput},
-    utils::{copy_directory, get_citrea_path, get_genesis_path},
-    Result,
+    registry::{container_status, NodeStatus},
+    resources::{ResourceSampler, SampleSource},
+    supervisor::Supervisor,
+    traits::{stop_spawn_output, NodeT, Restart, SpawnOutput, StopReport, DEFAULT_READY_TIMEOUT},
+    utils::{copy_directory, get_genesis_path},
+    wait::Wait,
+    Error, Result,
 };
 
 #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
@@ -79,6 +86,9 @@ where
     pub client: Client,
     // Bitcoin client targetting node's wallet endpoint
     pub da: BitcoinClient,
+    docker: Arc<Option<DockerEnv>>,
+    supervisor: Arc<Supervisor>,
+    resources: Arc<ResourceSampler>,
 }
 
 impl<C> Node<C>
@@ -89,10 +99,13 @@ where
         config: &FullL2NodeConfig<C>,
         da_config: &BitcoinConfig,
         docker: Arc<Option<DockerEnv>>,
+        supervisor: Arc<Supervisor>,
+        resources: Arc<ResourceSampler>,
     ) -> Result<Self> {
         let spawn_output = <Self as NodeT>::spawn(config, &docker).await?;
 
-        let client = Client::new(config.rpc_bind_host(), config.rpc_bind_port())?;
+        let client =
+            Client::new(config.rpc_bind_host(), config.rpc_bind_port())?.with_node(config.kind());
 
         let da_rpc_url = format!(
             "http://127.0.0.1:{}/wallet/{}",
@@ -106,23 +119,70 @@ where
         .await
         .context("Failed to create RPC client")?;
 
-        Ok(Self {
+        let node = Self {
             spawn_output,
             config: config.clone(),
             client,
             da: da_client,
-        })
+            docker,
+            supervisor,
+            resources,
+        };
+        node.supervise().await;
+        Ok(node)
+    }
+
+    // Supervise the local process, containers being supervised by `DockerEnv`,
+    // and sample the node resource usage
+    async fn supervise(&self) {
+        let Some(source) = self.sample_source() else {
+            return;
+        };
+        if let SampleSource::Process(pid) = &source {
+            self.supervisor
+                .watch(
+                    *pid,
+                    self.config.kind(),
+                    self.config.log_path(),
+                    self.config.stderr_path(),
+                )
+                .await;
+        }
+        // Containers mount their volume on the same storage path
+        let storage_dir = self.config.rollup.storage.path.clone();
+        self.resources
+            .track(source, self.config.kind(), storage_dir)
+            .await;
+    }
+
+    /// Key of this node resource samples, see `ResourceSampler::node_samples`.
+    /// Changes on restart, `None` once the local process was reaped.
+    pub fn sample_source(&self) -> Option<SampleSource> {
+        match &self.spawn_output {
+            SpawnOutput::Child(process) => process.id().map(SampleSource::Process),
+            SpawnOutput::Container(output) => Some(SampleSource::Container(output.id.clone())),
+        }
+    }
+
+    // Flag the next exit of the local process as requested
+    async fn expect_exit(&self) {
+        if let SpawnOutput::Child(process) = &self.spawn_output {
+            if let Some(pid) = process.id() {
+                self.supervisor.expect_exit(pid).await;
+            }
+        }
     }
 
     fn spawn(config: &FullL2NodeConfig<C>, extra_args: Option<Vec<String>>) -> Result<SpawnOutput> {
-        let citrea = get_citrea_path()?;
+        let citrea = config.citrea_path()?;
 
         let kind = config.kind();
 
         debug!("Spawning {kind} with config {config:?}");
 
+        // Logs of previous runs on the same dir, i.e. before a crash, are kept
         let stdout_path = config.log_path();
-        let stdout_file = File::create(&stdout_path).context("Failed to create stdout file")?;
+        let stdout_file = open_log_file(kind, &stdout_path)?;
         info!(
             "{} stdout logs available at : {}",
             kind,
@@ -130,60 +190,115 @@ where
         );
 
         let stderr_path = config.stderr_path();
-        let stderr_file = File::create(stderr_path).context("Failed to create stderr file")?;
+        let stderr_file = open_log_file(kind, &stderr_path)?;
 
-        Command::new(citrea)
-            .args(get_citrea_args(config))
+        let mut cmd = Command::new(citrea);
+        cmd.args(get_citrea_args(config))
             .args(extra_args.unwrap_or_default())
             .envs(config.env())
             .stdout(Stdio::from(stdout_file))
             .stderr(Stdio::from(stderr_file))
-            .kill_on_drop(true)
-            .spawn()
-            .context(format!("Failed to spawn {kind} process"))
-            .map(SpawnOutput::Child)
+            .kill_on_drop(true);
+        Ok(SpawnOutput::Child(cmd.spawn().map_err(|e| {
+            Error::SpawnFailed {
+                node: kind,
+                source: e.into(),
+            }
+        })?))
     }
 
     pub async fn wait_for_l2_height(&self, num: u64, timeout: Option<Duration>) -> Result<()> {
-        let start = SystemTime::now();
-        let timeout = timeout.unwrap_or(Duration::from_secs(30)); // Default 30 seconds timeout
-        loop {
-            trace!("Waiting for l2 block {}", num);
-            let latest_block = self.client.ledger_get_head_l2_block_height().await?;
-
-            if latest_block >= num {
-                break;
-            }
+        Wait::new(format!("L2 block {num}"))
+            .with_node(self.config.kind())
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(30)))
+            .until(
+                || self.client.ledger_get_head_l2_block_height(),
+                |latest_block| *latest_block >= num,
+            )
+            .await
+            .map(drop)
+    }
 
-            let now = SystemTime::now();
-            if start + timeout <= now {
-                bail!("Timeout. Latest L2 block is {:?}", latest_block);
-            }
+    /// Wait for the L2 activation height of `fork`, as reported by the node
+    pub async fn wait_for_fork(&self, fork: &str, timeout: Option<Duration>) -> Result<()> {
+        let height = self.fork_activation_height(fork).await?;
+        self.wait_for_l2_height(height, timeout).await
+    }
 
-            sleep(Duration::from_secs(1)).await;
-        }
+    pub async fn fork_activation_height(&self, fork: &str) -> Result<u64> {
+        self.client
+            .citrea_get_forks()
+            .await?
+            .activation_height(fork)
+            .with_context(|| format!("Fork {fork} is unknown to {}", self.config.kind()))
+    }
+
+    /// Wait for the node to meet `readiness`, logging what it is waiting for along the way
+    pub async fn wait_until_ready(&self, readiness: Readiness, timeout: Duration) -> Result<()> {
+        let kind = self.config.kind();
+        let start = Instant::now();
+        info!("Waiting for {kind} to be {readiness}");
+
+        Wait::new(format!("to be {readiness}"))
+            .with_node(kind)
+            .with_timeout(timeout)
+            .with_interval(Duration::from_millis(500))
+            .with_progress(READY_PROGRESS_INTERVAL)
+            .retry_all_errors()
+            .until(
+                || async {
+                    match readiness {
+                        Readiness::Reachable | Readiness::L2Synced(_) => {
+                            self.client.ledger_get_head_l2_block_height().await
+                        }
+                        Readiness::L1Scanned(_) => {
+                            self.client.ledger_get_last_scanned_l1_height().await
+                        }
+                    }
+                },
+                |height| match readiness {
+                    Readiness::Reachable => true,
+                    Readiness::L2Synced(target) | Readiness::L1Scanned(target) => *height >= target,
+                },
+            )
+            .await?;
+        info!("{kind} is {readiness} after {:?}", start.elapsed());
         Ok(())
     }
 
     pub async fn wait_for_l1_height(&self, height: u64, timeout: Option<Duration>) -> Result<()> {
-        let start = SystemTime::now();
-        let timeout = timeout.unwrap_or(Duration::from_secs(600));
-        loop {
-            trace!("Waiting for batch prover height {}", height);
-            let latest_block = self.client.ledger_get_last_scanned_l1_height().await?;
-
-            if latest_block >= height {
-                break;
-            }
+        Wait::new(format!("L1 height {height}"))
+            .with_node(self.config.kind())
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(600)))
+            .until(
+                || self.client.ledger_get_last_scanned_l1_height(),
+                |latest_block| *latest_block >= height,
+            )
+            .await
+            .map(drop)
+    }
+}
 
-            let now = SystemTime::now();
-            if start + timeout <= now {
-                bail!("Timeout. Latest batch prover L1 height is {}", latest_block);
-            }
+const READY_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Condition a node has to meet to be considered ready, see `Node::wait_until_ready`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    /// RPC answers `ledger_getHeadL2BlockHeight`
+    Reachable,
+    /// L2 head reached `height`, i.e. the sequencer head for a full node
+    L2Synced(u64),
+    /// Last scanned L1 block reached `height`, i.e. the finalized initial DA height for a prover
+    L1Scanned(u64),
+}
 
-            sleep(Duration::from_secs(1)).await;
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Readiness::Reachable => write!(f, "reachable"),
+            Readiness::L2Synced(height) => write!(f, "synced to L2 height {height}"),
+            Readiness::L1Scanned(height) => write!(f, "scanned to L1 height {height}"),
         }
-        Ok(())
     }
 }
 
@@ -198,7 +313,16 @@ where
 
     async fn spawn(config: &Self::Config, docker: &Arc<Option<DockerEnv>>) -> Result<SpawnOutput> {
         match docker.as_ref() {
-            Some(docker) if docker.citrea() => docker.spawn(config.to_owned().into()).await,
+            Some(docker) if docker.dockerized(config.kind()) => docker
+                .spawn(config.to_owned().into())
+                .await
+                .map_err(|source| {
+                    Error::SpawnFailed {
+                        node: config.kind(),
+                        source,
+                    }
+                    .into()
+                }),
             _ => Self::spawn(config, None),
         }
     }
@@ -207,30 +331,29 @@ where
         &mut self.spawn_output
     }
 
-    async fn wait_for_ready(&self, timeout: Option<Duration>) -> Result<()> {
-        let start = Instant::now();
-        let timeout = timeout.unwrap_or(Duration::from_secs(30));
-        let mut response = Err(anyhow!("initial response value"));
+    fn stop_timeout(&self) -> Duration {
+        self.config.base.stop_timeout
+    }
 
-        while response.is_err() && (start.elapsed() < timeout) {
-            response = self.client.ledger_get_head_l2_block_height().await;
-            sleep(Duration::from_millis(500)).await;
-        }
-        match response {
-            Ok(_) => return Ok(()),
-            Err(e) => anyhow::bail!(
-                "{} failed to become ready within the specified timeout, latest ledger_get_head_l2_block_height error: {}",
-                self.config.kind(),
-                e
-            )
-        }
+    async fn stop(&mut self) -> Result<StopReport> {
+        self.expect_exit().await;
+        let timeout = self.stop_timeout();
+        stop_spawn_output(&mut self.spawn_output, timeout).await
+    }
+
+    async fn wait_for_ready(&self, timeout: Option<Duration>) -> Result<()> {
+        self.wait_until_ready(
+            Readiness::Reachable,
+            timeout.unwrap_or(DEFAULT_READY_TIMEOUT),
+        )
+        .await
     }
 
     fn client(&self) -> &Self::Client {
         &self.client
     }
 
-    fn env(&self) -> Vec<(&'static str, &'static str)> {
+    fn env(&self) -> Vec<(String, String)> {
         self.config.env()
     }
 
@@ -251,10 +374,10 @@ where
 {
     async fn wait_until_stopped(&mut self) -> Result<()> {
         self.stop().await?;
-        match &mut self.spawn_output {
-            SpawnOutput::Child(pid) => pid.wait().await?,
-            SpawnOutput::Container(_) => unimplemented!("L2 nodes don't run in docker yet"),
-        };
+        // Containers are already stopped, and recreated by `start`
+        if let SpawnOutput::Child(pid) = &mut self.spawn_output {
+            pid.wait().await?;
+        }
         Ok(())
     }
 
@@ -284,8 +407,170 @@ where
         config.set_dir(new_dir);
 
         *self.spawn_output() = Self::spawn(config, extra_args)?;
+        self.supervise().await;
         self.wait_for_ready(None).await
     }
+
+    /// Containers run `binary` only with `CitreaImageSource::LocalBinary`, see `Node::upgrade_image`
+    async fn upgrade(&mut self, binary: PathBuf) -> Result<()> {
+        let kind = self.config.kind();
+        if let SpawnOutput::Container(_) = self.spawn_output {
+            if !matches!(
+                docker_env(&self.docker)?.citrea_image(),
+                CitreaImageSource::LocalBinary { .. }
+            ) {
+                bail!("{kind} runs from a docker image, upgrade it with Node::upgrade_image");
+            }
+        }
+        info!("Upgrading {kind} to {}", binary.display());
+        let config = self.config.clone().with_binary(binary);
+        self.upgrade_to(config).await
+    }
+}
+
+impl<C> Node<C>
+where
+    C: Clone + Serialize + Debug + Send + Sync,
+    DockerConfig: From<FullL2NodeConfig<C>>,
+{
+    /// Kill the node with SIGKILL and wait for it to exit, simulating a crash
+    pub async fn crash(&mut self) -> Result<()> {
+        let kind = self.config.kind();
+        self.expect_exit().await;
+        match &mut self.spawn_output {
+            SpawnOutput::Child(process) => {
+                info!("Crashing {kind} process {:?}", process.id());
+                process
+                    .kill()
+                    .await
+                    .with_context(|| format!("Failed to kill {kind} process"))?;
+            }
+            SpawnOutput::Container(ContainerSpawnOutput { id, .. }) => {
+                info!("Crashing {kind} container {id}");
+                let docker = docker_client(&self.docker)?;
+                docker
+                    .kill_container(
+                        id,
+                        Some(KillContainerOptions {
+                            signal: "SIGKILL".to_string(),
+                        }),
+                    )
+                    .await
+                    .map_err(Error::docker(kind))
+                    .with_context(|| format!("Failed to kill {kind} container"))?;
+                // Non zero exit codes are reported as errors
+                let _ = docker
+                    .wait_container(id, None::<WaitContainerOptions>)
+                    .collect::<Vec<_>>()
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the node process or container is still up
+    pub async fn status(&self) -> Result<NodeStatus> {
+        match &self.spawn_output {
+            SpawnOutput::Child(process) => {
+                // Missing once the process was reaped
+                let Some(pid) = process.id() else {
+                    return Ok(NodeStatus::Exited {
+                        exit_code: None,
+                        signal: None,
+                    });
+                };
+                Ok(match self.supervisor.process_exit(pid).await {
+                    Some(exit) => NodeStatus::Exited {
+                        exit_code: exit.exit_code,
+                        signal: exit.signal,
+                    },
+                    None => NodeStatus::Running,
+                })
+            }
+            SpawnOutput::Container(ContainerSpawnOutput { id, .. }) => {
+                container_status(docker_client(&self.docker)?, id, self.config.kind()).await
+            }
+        }
+    }
+
+    /// Start the node again from its current dir, reusing its storage in place.
+    /// Unlike `Restart::start` which copies the node dir to a new `{kind}-{INDEX}` dir.
+    /// Containers are recreated on the same volume.
+    pub async fn start_in_place(
+        &mut self,
+        new_config: Option<FullL2NodeConfig<C>>,
+        extra_args: Option<Vec<String>>,
+    ) -> Result<()> {
+        if let Some(new_config) = new_config {
+            new_config.write_configs()?;
+            self.config = new_config;
+        }
+
+        self.spawn_output = match &self.spawn_output {
+            SpawnOutput::Child(_) => Self::spawn(&self.config, extra_args)?,
+            SpawnOutput::Container(ContainerSpawnOutput { id, .. }) => {
+                let mut config = DockerConfig::from(self.config.clone());
+                config.cmd.extend(extra_args.unwrap_or_default());
+                docker_env(&self.docker)?
+                    .respawn(id, config)
+                    .await
+                    .map_err(|source| Error::SpawnFailed {
+                        node: self.config.kind(),
+                        source,
+                    })?
+            }
+        };
+        self.supervise().await;
+        self.wait_for_ready(None).await
+    }
+
+    /// Restart the container on its existing storage from docker `image`, see `Restart::upgrade`.
+    /// Requires `CitreaImageSource::Registry`, other sources not running the configured image.
+    pub async fn upgrade_image(&mut self, image: impl Into<String>) -> Result<()> {
+        let kind = self.config.kind();
+        let image = image.into();
+        if let SpawnOutput::Child(_) = self.spawn_output {
+            bail!("{kind} doesn't run in docker, upgrade it with Restart::upgrade");
+        }
+        if !matches!(
+            docker_env(&self.docker)?.citrea_image(),
+            CitreaImageSource::Registry
+        ) {
+            bail!("{kind} doesn't run from a registry image");
+        }
+        info!("Upgrading {kind} to image {image}");
+        let config = self.config.clone().with_docker_image(image);
+        self.upgrade_to(config).await
+    }
+
+    // Stop the node and start it again on the same storage with `config`
+    async fn upgrade_to(&mut self, config: FullL2NodeConfig<C>) -> Result<()> {
+        NodeT::stop(self).await?;
+        self.start_in_place(Some(config), None).await
+    }
+
+    /// Crash the node and restart it on the same storage, to check recovery from an unclean shutdown
+    pub async fn crash_restart(&mut self, extra_args: Option<Vec<String>>) -> Result<()> {
+        self.crash().await?;
+        self.start_in_place(None, extra_args).await
+    }
+}
+
+fn open_log_file(kind: NodeKind, path: &Path) -> Result<File> {
+    Ok(OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(Error::node_io(kind, path))?)
+}
+
+// Test docker env, containers only being spawned when it is set
+fn docker_env(docker: &Option<DockerEnv>) -> Result<&DockerEnv> {
+    docker.as_ref().context("Docker is not enabled")
+}
+
+pub(crate) fn docker_client(docker: &Option<DockerEnv>) -> Result<&Docker> {
+    docker_env(docker).map(|docker| &docker.docker)
 }
 
 pub fn get_citrea_args<C>(config: &FullL2NodeConfig<C>) -> Vec<String>
@@ -304,6 +589,7 @@ where
             "--genesis-paths".to_string(),
             get_genesis_path(config.dir()),
         ],
+        config.base.extra_args.clone(),
     ]
     .concat()
 }
@@ -0,0 +1,183 @@
+//! Pruning assertion helpers for L2 nodes running with `RunnerConfig::pruning_config`.
+
+use std::{fmt::Debug, time::Duration};
+
+use alloy_primitives::Address;
+use anyhow::{bail, Context};
+use serde::Serialize;
+
+use crate::{
+    config::{DockerConfig, FullL2NodeConfig, PruningConfig},
+    node::Node,
+    traits::Restart,
+    wait::Wait,
+    Error, Result,
+};
+
+// Code of citrea's resource not found errors, i.e. unknown block, returned for pruned data
+const RESOURCE_NOT_FOUND_CODE: i32 = -32001;
+
+// Messages of the errors returned for pruned data, matched in lowercase
+const NOT_SERVED_MESSAGES: [&str; 3] = ["not found", "pruned", "unknown block"];
+
+// Codes reserved by JSON-RPC, i.e. unknown method or internal error, never caused by pruned data
+const RESERVED_ERROR_CODES: std::ops::RangeInclusive<i32> = -32768..=-32600;
+
+// Whether `e` is the node reporting pruned or missing data, as opposed to a failed call
+fn is_not_served(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<Error>() {
+        Some(Error::Rpc {
+            code: Some(code),
+            message,
+            ..
+        }) => {
+            let message = message.to_lowercase();
+            *code == RESOURCE_NOT_FOUND_CODE
+                || (!RESERVED_ERROR_CODES.contains(code)
+                    && NOT_SERVED_MESSAGES
+                        .iter()
+                        .any(|pattern| message.contains(pattern)))
+        }
+        _ => false,
+    }
+}
+
+impl<C> Node<C>
+where
+    C: Clone + Debug + Serialize + Send + Sync,
+{
+    pub fn pruning_config(&self) -> Option<&PruningConfig> {
+        self.config
+            .rollup
+            .runner
+            .as_ref()
+            .and_then(|runner| runner.pruning_config.as_ref())
+    }
+
+    /// Highest L2 height expected to be pruned at the current head, `None` if nothing is prunable yet
+    pub async fn pruned_height(&self) -> Result<Option<u64>> {
+        let distance = self
+            .pruning_config()
+            .with_context(|| format!("Pruning is not enabled on {}", self.config.kind()))?
+            .distance;
+        let head = self.client.ledger_get_head_l2_block_height().await?;
+        Ok(head.checked_sub(distance).filter(|height| *height > 0))
+    }
+
+    /// Assert L2 block `height` and, if `address` is set, its state are no longer served.
+    /// Fails as well when the node can't be queried, only a null result or an error response counting as pruned.
+    pub async fn assert_pruned(&self, height: u64, address: Option<Address>) -> Result<()> {
+        match self.still_served(height, address).await? {
+            Some(served) => bail!("{served} is still served by {}", self.config.kind()),
+            None => Ok(()),
+        }
+    }
+
+    // What is still served of L2 block `height`, if anything
+    async fn still_served(&self, height: u64, address: Option<Address>) -> Result<Option<String>> {
+        match self.client.ledger_get_l2_block_by_number(height).await {
+            Ok(Some(_)) => return Ok(Some(format!("L2 block {height}"))),
+            Ok(None) => {}
+            Err(e) if is_not_served(&e) => {}
+            Err(e) => return Err(e),
+        }
+        if let Some(address) = address {
+            match self.client.eth_get_balance(address, height).await {
+                Ok(balance) => {
+                    return Ok(Some(format!(
+                        "State at L2 block {height}, balance of {address} being {balance},"
+                    )))
+                }
+                Err(e) if is_not_served(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Assert L2 block `height` and, if `address` is set, its state are still served
+    pub async fn assert_not_pruned(&self, height: u64, address: Option<Address>) -> Result<()> {
+        let kind = self.config.kind();
+        self.client
+            .ledger_get_l2_block_by_number(height)
+            .await?
+            .with_context(|| format!("L2 block {height} is not served by {kind}"))?;
+        if let Some(address) = address {
+            self.client
+                .eth_get_balance(address, height)
+                .await
+                .with_context(|| format!("State at L2 block {height} is not served by {kind}"))?;
+        }
+        Ok(())
+    }
+
+    /// Assert blocks older than the pruning distance are gone while the ones within it are served.
+    /// Checks the first block and the boundary blocks, i.e. `head - distance` and the one after.
+    pub async fn assert_pruning(&self, address: Option<Address>) -> Result<()> {
+        let Some(pruned_height) = self.pruned_height().await? else {
+            bail!("Nothing to prune yet on {}", self.config.kind());
+        };
+        self.assert_pruned(1, address).await?;
+        self.assert_pruned(pruned_height, address).await?;
+        self.assert_not_pruned(pruned_height + 1, address).await
+    }
+
+    /// Wait until blocks older than the pruning distance are gone, pruning running in the background
+    pub async fn wait_for_pruning(&self, timeout: Option<Duration>) -> Result<()> {
+        Wait::new("to prune")
+            .with_node(self.config.kind())
+            .with_timeout(timeout.unwrap_or(Duration::from_secs(30)))
+            .until_true(|| async {
+                Ok(match self.pruned_height().await? {
+                    Some(pruned_height) => self.still_served(pruned_height, None).await?.is_none(),
+                    None => false,
+                })
+            })
+            .await
+    }
+}
+
+impl<C> Node<C>
+where
+    C: Clone + Debug + Serialize + Send + Sync,
+    DockerConfig: From<FullL2NodeConfig<C>>,
+{
+    /// Restart a pruned node and wait for it to sync up to L2 `height`, pruning being kept
+    pub async fn restart_pruned(&mut self, height: u64, timeout: Option<Duration>) -> Result<()> {
+        self.restart(None, None).await?;
+        self.wait_for_l2_height(height, timeout).await?;
+        self.wait_for_pruning(timeout).await?;
+        self.assert_pruning(None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(code: i32, message: &str) -> anyhow::Error {
+        Error::Rpc {
+            node: None,
+            method: "eth_getBalance".to_string(),
+            code: Some(code),
+            message: message.to_string(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_is_not_served() {
+        assert!(is_not_served(&rpc_error(-32001, "unknown block number")));
+        assert!(is_not_served(&rpc_error(
+            -32000,
+            "State at height 10 was pruned"
+        )));
+        assert!(!is_not_served(&rpc_error(-32603, "Internal error")));
+        assert!(!is_not_served(&rpc_error(
+            -32603,
+            "Block not found in cache"
+        )));
+        assert!(!is_not_served(&rpc_error(-32601, "Method not found")));
+        assert!(!is_not_served(&anyhow::anyhow!("connection refused")));
+    }
+}
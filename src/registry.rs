@@ -0,0 +1,262 @@
+//! Uniform access to the nodes of a test, addressed by `NodeKind` and index.
+//!
+//! Index 0 of an L2 kind is the node started with the test, when enabled, followed by the nodes
+//! spawned during the test in spawn order. Bitcoin nodes are indexed as in `BitcoinNodeCluster`.
+
+use std::{fmt::Debug, time::Duration};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use bollard::{query_parameters::InspectContainerOptions, Docker};
+use serde::Serialize;
+
+use crate::{
+    bitcoin::BitcoinNode,
+    config::{DockerConfig, FullL2NodeConfig},
+    framework::TestFramework,
+    log_provider::{LogPathProvider, LogPathProviderErased},
+    node::{Node, NodeKind, Readiness},
+    resources::SampleSource,
+    traits::{NodeT, Restart, StopReport},
+    Error, Result,
+};
+
+/// Whether a node process or container is still up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStatus {
+    Running,
+    /// Exit status is unknown once a local process was reaped, i.e. after `stop`
+    Exited {
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// Object safe control over a node, whatever its kind and config
+#[async_trait]
+pub trait NodeErased: Send + Sync {
+    fn kind(&self) -> NodeKind;
+
+    fn log_provider(&self) -> &dyn LogPathProviderErased;
+
+    async fn status(&self) -> Result<NodeStatus>;
+
+    /// See `Node::sample_source`
+    fn sample_source(&self) -> Option<SampleSource>;
+
+    /// See `NodeT::stop`
+    async fn stop(&mut self) -> Result<StopReport>;
+
+    /// See `Restart::restart`, keeping the node config
+    async fn restart(&mut self, extra_args: Option<Vec<String>>) -> Result<()>;
+
+    /// See `NodeT::wait_for_ready`
+    async fn wait_for_ready(&self, timeout: Option<Duration>) -> Result<()>;
+
+    /// See `Node::wait_until_ready`, bitcoin nodes only supporting `Readiness::Reachable`
+    async fn wait_until_ready(&self, readiness: Readiness, timeout: Duration) -> Result<()>;
+}
+
+#[async_trait]
+impl<C> NodeErased for Node<C>
+where
+    C: Clone + Debug + Serialize + Send + Sync,
+    DockerConfig: From<FullL2NodeConfig<C>>,
+{
+    fn kind(&self) -> NodeKind {
+        self.config.kind()
+    }
+
+    fn log_provider(&self) -> &dyn LogPathProviderErased {
+        self.config.as_erased()
+    }
+
+    async fn status(&self) -> Result<NodeStatus> {
+        Node::status(self).await
+    }
+
+    fn sample_source(&self) -> Option<SampleSource> {
+        Node::sample_source(self)
+    }
+
+    async fn stop(&mut self) -> Result<StopReport> {
+        NodeT::stop(self).await
+    }
+
+    async fn restart(&mut self, extra_args: Option<Vec<String>>) -> Result<()> {
+        Restart::restart(self, None, extra_args).await
+    }
+
+    async fn wait_for_ready(&self, timeout: Option<Duration>) -> Result<()> {
+        NodeT::wait_for_ready(self, timeout).await
+    }
+
+    async fn wait_until_ready(&self, readiness: Readiness, timeout: Duration) -> Result<()> {
+        Node::wait_until_ready(self, readiness, timeout).await
+    }
+}
+
+#[async_trait]
+impl NodeErased for BitcoinNode {
+    fn kind(&self) -> NodeKind {
+        NodeKind::Bitcoin
+    }
+
+    fn log_provider(&self) -> &dyn LogPathProviderErased {
+        self.config.as_erased()
+    }
+
+    async fn status(&self) -> Result<NodeStatus> {
+        BitcoinNode::status(self).await
+    }
+
+    fn sample_source(&self) -> Option<SampleSource> {
+        None
+    }
+
+    async fn stop(&mut self) -> Result<StopReport> {
+        self.shutdown().await
+    }
+
+    async fn restart(&mut self, extra_args: Option<Vec<String>>) -> Result<()> {
+        Restart::restart(self, None, extra_args).await
+    }
+
+    async fn wait_for_ready(&self, timeout: Option<Duration>) -> Result<()> {
+        NodeT::wait_for_ready(self, timeout).await
+    }
+
+    async fn wait_until_ready(&self, readiness: Readiness, timeout: Duration) -> Result<()> {
+        if readiness != Readiness::Reachable {
+            bail!("Bitcoin node can't wait to be {readiness}");
+        }
+        NodeT::wait_for_ready(self, Some(timeout)).await
+    }
+}
+
+/// Status of container `id`, running node `kind`
+pub(crate) async fn container_status(
+    docker: &Docker,
+    id: &str,
+    kind: NodeKind,
+) -> Result<NodeStatus> {
+    let state = docker
+        .inspect_container(id, None::<InspectContainerOptions>)
+        .await
+        .map_err(Error::docker(kind))
+        .with_context(|| format!("Failed to inspect {kind} container"))?
+        .state
+        .unwrap_or_default();
+    Ok(if state.running.unwrap_or(false) {
+        NodeStatus::Running
+    } else {
+        NodeStatus::Exited {
+            exit_code: state.exit_code.map(|code| code as i32),
+            signal: None,
+        }
+    })
+}
+
+// Nodes spawned during the test, by kind then spawn order
+macro_rules! spawned_nodes {
+    ($self:ident, $iter:ident, $node:ty) => {
+        $self
+            .spawned_batch_provers
+            .$iter()
+            .map(|node| node as $node)
+            .chain(
+                $self
+                    .spawned_light_client_provers
+                    .$iter()
+                    .map(|node| node as $node),
+            )
+            .chain($self.spawned_full_nodes.$iter().map(|node| node as $node))
+    };
+}
+
+// L2 nodes started with the test in start order, then spawned L2 nodes, then bitcoin nodes
+macro_rules! all_nodes {
+    ($self:ident, $iter:ident, $node:ty) => {
+        $self
+            .sequencer
+            .$iter()
+            .map(|node| node as $node)
+            .chain($self.batch_prover.$iter().map(|node| node as $node))
+            .chain($self.light_client_prover.$iter().map(|node| node as $node))
+            .chain($self.full_node.$iter().map(|node| node as $node))
+            .chain(spawned_nodes!($self, $iter, $node))
+            .chain($self.bitcoin_nodes.$iter().map(|node| node as $node))
+    };
+}
+
+impl TestFramework {
+    /// All nodes, L2 nodes first so that they are stopped before their DA
+    pub fn nodes(&self) -> Vec<&dyn NodeErased> {
+        all_nodes!(self, iter, &dyn NodeErased).collect()
+    }
+
+    /// All nodes, see `nodes`
+    pub fn nodes_mut(&mut self) -> Vec<&mut dyn NodeErased> {
+        all_nodes!(self, iter_mut, &mut dyn NodeErased).collect()
+    }
+
+    /// L2 nodes spawned during the test, see `spawn_full_node`
+    pub fn spawned_nodes(&self) -> Vec<&dyn NodeErased> {
+        spawned_nodes!(self, iter, &dyn NodeErased).collect()
+    }
+
+    /// Nodes of `kind`, by index
+    pub fn nodes_of(&self, kind: NodeKind) -> Vec<&dyn NodeErased> {
+        self.nodes()
+            .into_iter()
+            .filter(|node| node.kind() == kind)
+            .collect()
+    }
+
+    /// Node `index` of `kind`
+    pub fn node(&self, kind: NodeKind, index: usize) -> Option<&dyn NodeErased> {
+        self.nodes_of(kind).into_iter().nth(index)
+    }
+
+    pub fn node_mut(&mut self, kind: NodeKind, index: usize) -> Option<&mut dyn NodeErased> {
+        self.nodes_mut()
+            .into_iter()
+            .filter(|node| node.kind() == kind)
+            .nth(index)
+    }
+
+    /// Log paths of the running nodes, see `nodes`
+    pub fn node_log_providers(&self) -> Vec<&dyn LogPathProviderErased> {
+        self.nodes()
+            .into_iter()
+            .map(|node| node.log_provider())
+            .collect()
+    }
+}
+
+/// Take L2 node `index` out of the test node of a kind and its spawned nodes, see `TestFramework::node`
+pub(crate) fn take_node<N>(node: &mut Option<N>, spawned: &mut Vec<N>, index: usize) -> Option<N> {
+    let index = match (node.is_some(), index) {
+        (true, 0) => return node.take(),
+        (true, index) => index - 1,
+        (false, index) => index,
+    };
+    (index < spawned.len()).then(|| spawned.remove(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_node() {
+        let mut node = Some(0);
+        let mut spawned = vec![1, 2];
+        assert_eq!(take_node(&mut node, &mut spawned, 3), None);
+        assert_eq!(take_node(&mut node, &mut spawned, 1), Some(1));
+        assert_eq!(take_node(&mut node, &mut spawned, 0), Some(0));
+        // Spawned nodes shift down once the test node is gone
+        assert_eq!(take_node(&mut node, &mut spawned, 0), Some(2));
+        assert!(spawned.is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+//! Background sampling of L2 nodes resource usage.
+//!
+//! Local processes are read from `/proc/<pid>`, containers through docker stats.
+//! Sampling is on by default, see `TestCaseConfig::resource_sample_interval`.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use bollard::{
+    container::LogOutput,
+    exec::{CreateExecOptions, StartExecResults},
+    models::ContainerMemoryStats,
+    query_parameters::StatsOptions,
+    Docker,
+};
+use futures::StreamExt;
+use serde::{Serialize, Serializer};
+use tokio::{sync::Mutex, task::JoinHandle, time::sleep};
+use tracing::trace;
+
+use crate::{node::NodeKind, Error, Result};
+
+/// Default interval between two samples
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+// `/proc/<pid>/stat` times are expressed in USER_HZ, fixed to 100 on Linux
+const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+const CSV_REPORT_FILE: &str = "resources.csv";
+const JSON_REPORT_FILE: &str = "resources.json";
+
+/// Where the resources of a node are read from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SampleSource {
+    Process(u32),
+    Container(String),
+}
+
+impl fmt::Display for SampleSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleSource::Process(pid) => write!(f, "{pid}"),
+            SampleSource::Container(id) => write!(f, "{id}"),
+        }
+    }
+}
+
+/// Resource usage of a node at a point in time, `None` when not available from its source
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceSample {
+    /// Time since the sampler started
+    pub elapsed_ms: u64,
+    #[serde(serialize_with = "serialize_display")]
+    pub kind: NodeKind,
+    /// Pid or container id
+    pub source: String,
+    pub cpu_time_ms: Option<u64>,
+    pub rss_bytes: Option<u64>,
+    pub open_fds: Option<u64>,
+    pub storage_bytes: Option<u64>,
+}
+
+/// Resource limits a node has to stay within, see `ResourceSampler::assert_budget`
+#[derive(Debug, Clone, Default)]
+pub struct ResourceBudget {
+    pub max_rss_bytes: Option<u64>,
+    pub max_open_fds: Option<u64>,
+    pub max_storage_bytes: Option<u64>,
+    pub max_cpu_time: Option<Duration>,
+}
+
+impl ResourceBudget {
+    pub fn with_max_rss(mut self, bytes: u64) -> Self {
+        self.max_rss_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_max_open_fds(mut self, fds: u64) -> Self {
+        self.max_open_fds = Some(fds);
+        self
+    }
+
+    pub fn with_max_storage(mut self, bytes: u64) -> Self {
+        self.max_storage_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_max_cpu_time(mut self, cpu_time: Duration) -> Self {
+        self.max_cpu_time = Some(cpu_time);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SampleTarget {
+    kind: NodeKind,
+    storage_dir: PathBuf,
+}
+
+/// Samples the resource usage of the tracked nodes on a fixed interval.
+/// Targets are dropped once their process or container is gone.
+pub struct ResourceSampler {
+    targets: Arc<Mutex<HashMap<SampleSource, SampleTarget>>>,
+    samples: Arc<Mutex<Vec<ResourceSample>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ResourceSampler {
+    /// Start sampling every `interval`, `None` disables sampling
+    pub fn new(interval: Option<Duration>, docker: Option<Docker>) -> Self {
+        let targets = Arc::new(Mutex::new(HashMap::new()));
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let handle = interval.map(|interval| {
+            Self::sample_targets(interval, docker, Arc::clone(&targets), Arc::clone(&samples))
+        });
+        Self {
+            targets,
+            samples,
+            handle,
+        }
+    }
+
+    fn sample_targets(
+        interval: Duration,
+        docker: Option<Docker>,
+        targets: Arc<Mutex<HashMap<SampleSource, SampleTarget>>>,
+        samples: Arc<Mutex<Vec<ResourceSample>>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let start = Instant::now();
+            loop {
+                sleep(interval).await;
+
+                let current = targets.lock().await.clone();
+                for (source, target) in current {
+                    let usage = match &source {
+                        SampleSource::Process(pid) => process_usage(*pid, &target.storage_dir),
+                        SampleSource::Container(id) => match &docker {
+                            Some(docker) => container_usage(docker, id, &target.storage_dir).await,
+                            None => None,
+                        },
+                    };
+                    let Some(usage) = usage else {
+                        trace!("{} {source:?} is gone, no longer sampled", target.kind);
+                        targets.lock().await.remove(&source);
+                        continue;
+                    };
+
+                    samples.lock().await.push(ResourceSample {
+                        elapsed_ms: start.elapsed().as_millis() as u64,
+                        kind: target.kind,
+                        source: source.to_string(),
+                        cpu_time_ms: usage.cpu_time_ms,
+                        rss_bytes: usage.rss_bytes,
+                        open_fds: usage.open_fds,
+                        storage_bytes: usage.storage_bytes,
+                    });
+                }
+            }
+        })
+    }
+
+    /// Start sampling node `kind` from `source`, `storage_dir` being its DB path, within the container if any
+    pub async fn track(&self, source: SampleSource, kind: NodeKind, storage_dir: PathBuf) {
+        if self.handle.is_none() {
+            return;
+        }
+        self.targets
+            .lock()
+            .await
+            .insert(source, SampleTarget { kind, storage_dir });
+    }
+
+    pub async fn samples(&self) -> Vec<ResourceSample> {
+        self.samples.lock().await.clone()
+    }
+
+    /// Samples of the node running as `source`, see `Node::sample_source`
+    pub async fn node_samples(&self, source: &SampleSource) -> Vec<ResourceSample> {
+        let source = source.to_string();
+        self.samples
+            .lock()
+            .await
+            .iter()
+            .filter(|sample| sample.source == source)
+            .cloned()
+            .collect()
+    }
+
+    /// Assert every sample of the node running as `source` is within `budget`
+    pub async fn assert_budget(
+        &self,
+        source: &SampleSource,
+        budget: &ResourceBudget,
+    ) -> Result<()> {
+        let samples = self.node_samples(source).await;
+        let Some(kind) = samples.first().map(|sample| sample.kind) else {
+            bail!("No resource sample for {source:?}");
+        };
+
+        let max = |f: fn(&ResourceSample) -> Option<u64>| samples.iter().filter_map(f).max();
+        let checks = [
+            ("RSS bytes", max(|s| s.rss_bytes), budget.max_rss_bytes),
+            ("open fds", max(|s| s.open_fds), budget.max_open_fds),
+            (
+                "storage bytes",
+                max(|s| s.storage_bytes),
+                budget.max_storage_bytes,
+            ),
+            (
+                "CPU time ms",
+                max(|s| s.cpu_time_ms),
+                budget.max_cpu_time.map(|t| t.as_millis() as u64),
+            ),
+        ];
+        for (resource, peak, limit) in checks {
+            if let (Some(peak), Some(limit)) = (peak, limit) {
+                if peak > limit {
+                    bail!("{kind} {resource} peaked at {peak}, over budget {limit}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut csv =
+            "elapsed_ms,kind,source,cpu_time_ms,rss_bytes,open_fds,storage_bytes\n".to_string();
+        let field = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+        for sample in self.samples.lock().await.iter() {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{}",
+                sample.elapsed_ms,
+                sample.kind,
+                sample.source,
+                field(sample.cpu_time_ms),
+                field(sample.rss_bytes),
+                field(sample.open_fds),
+                field(sample.storage_bytes),
+            )?;
+        }
+        Ok(std::fs::write(path, csv).map_err(Error::io(path))?)
+    }
+
+    pub async fn write_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(&*self.samples.lock().await)?;
+        Ok(std::fs::write(path, json).map_err(Error::io(path))?)
+    }
+
+    /// Write `resources.csv` and `resources.json` reports into `dir`, if sampling is enabled
+    pub async fn write_report(&self, dir: &Path) -> Result<()> {
+        if self.handle.is_none() {
+            return Ok(());
+        }
+        self.write_csv(&dir.join(CSV_REPORT_FILE)).await?;
+        self.write_json(&dir.join(JSON_REPORT_FILE)).await
+    }
+}
+
+impl Drop for ResourceSampler {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.abort();
+        }
+    }
+}
+
+fn serialize_display<S: Serializer>(
+    kind: &NodeKind,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(kind)
+}
+
+#[derive(Debug)]
+struct Usage {
+    cpu_time_ms: Option<u64>,
+    rss_bytes: Option<u64>,
+    open_fds: Option<u64>,
+    storage_bytes: Option<u64>,
+}
+
+// `None` once the process exited
+fn process_usage(pid: u32, storage_dir: &Path) -> Option<Usage> {
+    let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+    let stat = std::fs::read_to_string(proc_dir.join("stat")).ok()?;
+    let status = std::fs::read_to_string(proc_dir.join("status")).ok()?;
+    let (cpu_time_ms, zombie) = parse_stat_cpu_time(&stat)?;
+    if zombie {
+        return None;
+    }
+
+    Some(Usage {
+        cpu_time_ms: Some(cpu_time_ms),
+        rss_bytes: parse_status_rss(&status),
+        open_fds: std::fs::read_dir(proc_dir.join("fd"))
+            .ok()
+            .map(|fds| fds.count() as u64),
+        storage_bytes: dir_size(storage_dir),
+    })
+}
+
+// `None` once the container is gone
+async fn container_usage(docker: &Docker, id: &str, storage_dir: &Path) -> Option<Usage> {
+    let options = StatsOptions {
+        stream: false,
+        one_shot: true,
+    };
+    let stats = docker.stats(id, Some(options)).next().await?.ok()?;
+    // Stopped containers report no memory usage
+    let rss_bytes = container_rss(&stats.memory_stats?)?;
+
+    Some(Usage {
+        cpu_time_ms: stats
+            .cpu_stats
+            .and_then(|cpu| cpu.cpu_usage)
+            .and_then(|usage| usage.total_usage)
+            .map(|total_usage| total_usage / 1_000_000),
+        rss_bytes: Some(rss_bytes),
+        open_fds: None,
+        storage_bytes: container_dir_size(docker, id, storage_dir).await,
+    })
+}
+
+// Memory usage without the page cache, as reported by `docker stats`
+fn container_rss(memory_stats: &ContainerMemoryStats) -> Option<u64> {
+    let usage = memory_stats.usage?;
+    // `inactive_file` on cgroup v2, `total_inactive_file` on cgroup v1
+    let inactive_file = memory_stats
+        .stats
+        .as_ref()
+        .and_then(|stats| {
+            stats
+                .get("inactive_file")
+                .or_else(|| stats.get("total_inactive_file"))
+        })
+        .copied()
+        .unwrap_or(0);
+    Some(usage.saturating_sub(inactive_file))
+}
+
+// Size of `dir` within the container, through `du`
+async fn container_dir_size(docker: &Docker, id: &str, dir: &Path) -> Option<u64> {
+    let exec = docker
+        .create_exec(
+            id,
+            CreateExecOptions {
+                cmd: Some(vec![
+                    "du".to_string(),
+                    "-sb".to_string(),
+                    dir.display().to_string(),
+                ]),
+                attach_stdout: Some(true),
+                ..Default::default()
+            },
+        )
+        .await
+        .ok()?;
+    let StartExecResults::Attached { mut output, .. } =
+        docker.start_exec(&exec.id, None).await.ok()?
+    else {
+        return None;
+    };
+
+    let mut stdout = String::new();
+    while let Some(Ok(log)) = output.next().await {
+        if let LogOutput::StdOut { message } = log {
+            stdout.push_str(&String::from_utf8_lossy(&message));
+        }
+    }
+    parse_du_size(&stdout)
+}
+
+fn parse_du_size(du: &str) -> Option<u64> {
+    du.split_whitespace().next()?.parse().ok()
+}
+
+// CPU time from utime and stime, and whether the process is a zombie
+fn parse_stat_cpu_time(stat: &str) -> Option<(u64, bool)> {
+    // Process name is between parentheses and can contain spaces
+    let fields: Vec<&str> = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .collect();
+    // Fields after the name start at `state`, field 3 of proc(5)
+    let zombie = fields.first() == Some(&"Z");
+    let utime: u64 = fields.get(14 - 3)?.parse().ok()?;
+    let stime: u64 = fields.get(15 - 3)?.parse().ok()?;
+    Some(((utime + stime) * 1000 / CLOCK_TICKS_PER_SEC, zombie))
+}
+
+fn parse_status_rss(status: &str) -> Option<u64> {
+    let kb: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+fn dir_size(dir: &Path) -> Option<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir).ok()? {
+        let entry = entry.ok()?;
+        let metadata = entry.metadata().ok()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path()).unwrap_or(0)
+        } else {
+            metadata.len()
+        };
+    }
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("CURRENT"), [0; 16]).unwrap();
+        let usage = process_usage(std::process::id(), dir.path()).unwrap();
+        assert!(usage.rss_bytes.unwrap() > 0);
+        assert!(usage.open_fds.unwrap() > 0);
+        assert_eq!(usage.storage_bytes, Some(16));
+
+        let (cpu_time_ms, zombie) =
+            parse_stat_cpu_time("42 (citrea (main)) R 1 2 3 4 5 6 7 8 9 10 150 50 0").unwrap();
+        assert_eq!(cpu_time_ms, 2000);
+        assert!(!zombie);
+        assert_eq!(
+            parse_status_rss("Name:\tcitrea\nVmRSS:\t  2048 kB\n"),
+            Some(2 * 1024 * 1024)
+        );
+    }
+
+    #[test]
+    fn test_container_usage() {
+        let memory_stats = |stats: &[(&str, u64)]| ContainerMemoryStats {
+            usage: Some(1000),
+            stats: Some(stats.iter().map(|(k, v)| (k.to_string(), *v)).collect()),
+            ..Default::default()
+        };
+        assert_eq!(
+            container_rss(&memory_stats(&[("inactive_file", 300)])),
+            Some(700)
+        );
+        assert_eq!(
+            container_rss(&memory_stats(&[("total_inactive_file", 200)])),
+            Some(800)
+        );
+        assert_eq!(container_rss(&memory_stats(&[])), Some(1000));
+
+        assert_eq!(parse_du_size("4096\t/mnt/task/storage\n"), Some(4096));
+        assert_eq!(parse_du_size(""), None);
+    }
+}
@@ -0,0 +1,283 @@
+//! Supervision of locally spawned citrea processes.
+//!
+//! Containers are supervised through docker events, see `DockerEnv`.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    sync::{watch, Mutex},
+    task::JoinHandle,
+    time::sleep,
+};
+use tracing::{debug, error};
+
+use crate::{node::NodeKind, utils::tail_lines, Error};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exit of a supervised process
+#[derive(Debug, Clone)]
+pub struct ProcessExit {
+    pub pid: u32,
+    pub kind: NodeKind,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// Whether the exit followed a requested stop, crash or restart
+    pub requested: bool,
+    pub log_path: PathBuf,
+    pub stderr_path: PathBuf,
+}
+
+impl fmt::Display for ProcessExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} process {} exited", self.kind, self.pid)?;
+        if let Some(exit_code) = self.exit_code {
+            write!(f, " with exit code {exit_code}")?;
+        }
+        if let Some(signal) = self.signal {
+            write!(f, " killed by signal {signal}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ProcessState {
+    kind: NodeKind,
+    log_path: PathBuf,
+    stderr_path: PathBuf,
+    stop_requested: bool,
+    exit: Option<ProcessExit>,
+}
+
+/// Watches the local citrea processes of a test and keeps track of their exit status.
+/// Exits that don't follow a call to `expect_exit` are reported as unexpected.
+///
+/// Processes are polled with `waitid` as their `Child` handle is owned by the node.
+pub struct Supervisor {
+    processes: Arc<Mutex<HashMap<u32, ProcessState>>>,
+    unexpected_exit: Arc<watch::Sender<Option<ProcessExit>>>,
+    handle: JoinHandle<()>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let processes = Arc::new(Mutex::new(HashMap::new()));
+        let unexpected_exit = Arc::new(watch::Sender::new(None));
+        let handle = Self::poll_processes(Arc::clone(&processes), Arc::clone(&unexpected_exit));
+        Self {
+            processes,
+            unexpected_exit,
+            handle,
+        }
+    }
+
+    fn poll_processes(
+        processes: Arc<Mutex<HashMap<u32, ProcessState>>>,
+        unexpected_exit: Arc<watch::Sender<Option<ProcessExit>>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                sleep(POLL_INTERVAL).await;
+
+                let mut processes = processes.lock().await;
+                for (pid, state) in processes.iter_mut() {
+                    if state.exit.is_some() {
+                        continue;
+                    }
+                    let Some(status) = exit_status(*pid) else {
+                        continue;
+                    };
+
+                    let exit = ProcessExit {
+                        pid: *pid,
+                        kind: state.kind,
+                        exit_code: status.exit_code,
+                        signal: status.signal,
+                        requested: state.stop_requested,
+                        log_path: state.log_path.clone(),
+                        stderr_path: state.stderr_path.clone(),
+                    };
+                    if exit.requested {
+                        debug!("{exit}");
+                    } else {
+                        error!("{exit} unexpectedly");
+                        unexpected_exit.send_replace(Some(exit.clone()));
+                    }
+                    state.exit = Some(exit);
+                }
+            }
+        })
+    }
+
+    /// Start supervising process `pid`
+    pub async fn watch(&self, pid: u32, kind: NodeKind, log_path: PathBuf, stderr_path: PathBuf) {
+        self.processes.lock().await.insert(
+            pid,
+            ProcessState {
+                kind,
+                log_path,
+                stderr_path,
+                stop_requested: false,
+                exit: None,
+            },
+        );
+    }
+
+    /// Flag the next exit of process `pid` as requested.
+    /// Has to be called before the process is signaled.
+    pub async fn expect_exit(&self, pid: u32) {
+        if let Some(state) = self.processes.lock().await.get_mut(&pid) {
+            state.stop_requested = true;
+        }
+    }
+
+    /// Returns the exit of process `pid`, if it exited
+    pub async fn process_exit(&self, pid: u32) -> Option<ProcessExit> {
+        self.processes
+            .lock()
+            .await
+            .get(&pid)
+            .and_then(|state| state.exit.clone())
+    }
+
+    /// Resolves with the first process exit that wasn't requested
+    pub async fn wait_for_unexpected_exit(&self) -> ProcessExit {
+        let mut rx = self.unexpected_exit.subscribe();
+        loop {
+            if let Some(exit) = rx.borrow_and_update().clone() {
+                return exit;
+            }
+            if rx.changed().await.is_err() {
+                return std::future::pending().await;
+            }
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct ExitStatus {
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+}
+
+// Exit status of child `pid` if it exited.
+// `WNOWAIT` leaves the child to be reaped by its `Child` handle, which also keeps the PID from
+// being reused while it's watched. The status is unknown when the child was already reaped.
+fn exit_status(pid: u32) -> Option<ExitStatus> {
+    // SAFETY: `siginfo_t` is plain data and `waitid` only writes to `info`
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            pid,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    // `si_pid` stays zero while the child is running
+    if ret != 0 || unsafe { info.si_pid() } == 0 {
+        return None;
+    }
+
+    let status = unsafe { info.si_status() };
+    match info.si_code {
+        libc::CLD_EXITED => Some(ExitStatus {
+            exit_code: Some(status),
+            signal: None,
+        }),
+        libc::CLD_KILLED | libc::CLD_DUMPED => Some(ExitStatus {
+            exit_code: None,
+            signal: Some(status),
+        }),
+        _ => None,
+    }
+}
+
+/// Last `TAIL_N_LINES` stdout and stderr lines of a node, for `Error::NodeExited`
+pub(crate) fn log_tails(log_path: &Path, stderr_path: &Path) -> (usize, String, String) {
+    let n_lines = std::env::var("TAIL_N_LINES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(50);
+    let tail = |path: &Path| {
+        tail_lines(path, n_lines)
+            .map(|lines| lines.join("\n"))
+            .unwrap_or_else(|e| format!("Failed to read {}: {e}", path.display()))
+    };
+    (n_lines, tail(log_path), tail(stderr_path))
+}
+
+impl From<&ProcessExit> for Error {
+    fn from(exit: &ProcessExit) -> Self {
+        let (tail_lines, stdout_tail, stderr_tail) = log_tails(&exit.log_path, &exit.stderr_path);
+        Error::NodeExited {
+            node: exit.kind,
+            id: format!("process {}", exit.pid),
+            exit_code: exit.exit_code.map(i64::from),
+            signal: exit.signal,
+            oom_killed: false,
+            log_path: exit.log_path.clone(),
+            stderr_path: exit.stderr_path.clone(),
+            stdout_tail,
+            stderr_tail,
+            tail_lines,
+        }
+    }
+}
+
+/// Waits for any supervised process to exit unexpectedly and turns it into `Error::NodeExited`
+pub(crate) async fn wait_for_unexpected_exit(supervisor: &Supervisor) -> anyhow::Error {
+    let exit = supervisor.wait_for_unexpected_exit().await;
+    Error::from(&exit).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_status() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        let status = loop {
+            if let Some(status) = exit_status(pid) {
+                break status;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(
+            status,
+            ExitStatus {
+                exit_code: Some(3),
+                signal: None
+            }
+        );
+
+        // Still reapable by its owner
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+        assert_eq!(exit_status(pid), None);
+    }
+}
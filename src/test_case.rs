@@ -5,12 +5,12 @@ use std::{
     io::Write,
     panic::{self},
     path::Path,
-    time::Duration,
+    sync::Arc,
 };
 
 use anyhow::{bail, Context};
 use async_trait::async_trait;
-use futures::FutureExt;
+use futures::{future::BoxFuture, FutureExt};
 use tokio::signal;
 
 use super::{
@@ -19,8 +19,13 @@ use super::{
     Result,
 };
 use crate::{
-    config::{BatchProverConfig, LightClientProverConfig, SequencerConfig},
-    traits::NodeT,
+    config::{
+        BatchProverConfig, EmptyConfig, LightClientProverConfig, PruningConfig, RollupConfig,
+        SequencerConfig, TestCaseDefinition, PREVIOUS_CITREA_ENV,
+    },
+    docker::wait_for_unexpected_exit,
+    node::NodeKind,
+    supervisor::wait_for_unexpected_exit as wait_for_unexpected_process_exit,
 };
 
 const CITREA_ENV: &str = "CITREA_E2E_TEST_BINARY";
@@ -45,29 +50,7 @@ impl<T: TestCase> TestCaseRunner<T> {
         f.fund_da_wallets().await?;
         f.init_nodes().await?;
         f.bitcoin_nodes.connect_nodes().await?;
-
-        if let Some(sequencer) = &f.sequencer {
-            sequencer
-                .wait_for_ready(Some(Duration::from_secs(5)))
-                .await?;
-        }
-        if let Some(batch_prover) = &f.batch_prover {
-            batch_prover
-                .wait_for_ready(Some(Duration::from_secs(5)))
-                .await?;
-        }
-        if let Some(light_client_prover) = &f.light_client_prover {
-            light_client_prover
-                .wait_for_ready(Some(Duration::from_secs(5)))
-                .await?;
-        }
-        if let Some(full_node) = &f.full_node {
-            full_node
-                .wait_for_ready(Some(Duration::from_secs(5)))
-                .await?;
-        }
-
-        Ok(())
+        f.wait_for_nodes_ready().await
     }
 
     async fn run_test_case(&mut self, f: &mut TestFramework) -> Result<()> {
@@ -79,7 +62,14 @@ impl<T: TestCase> TestCaseRunner<T> {
     /// Executes the test case, handling any panics and performing cleanup.
     ///
     /// This sets up the framework, executes the test, and ensures cleanup is performed even if a panic occurs.
-    pub async fn run(mut self) -> Result<()> {
+    pub async fn run(self) -> Result<()> {
+        match self.0.definition() {
+            Some(definition) => DEFINITION.scope(definition, self.run_inner()).await,
+            None => self.run_inner().await,
+        }
+    }
+
+    async fn run_inner(mut self) -> Result<()> {
         let mut framework = None;
 
         let result = panic::AssertUnwindSafe(async {
@@ -87,7 +77,14 @@ impl<T: TestCase> TestCaseRunner<T> {
                 res = async {
                     framework = Some(TestFramework::new::<T>().await?);
                     let f = framework.as_mut().unwrap();
-                    self.run_test_case(f).await
+                    let docker = f.docker();
+                    let supervisor = f.supervisor();
+                    tokio::select! {
+                        res = self.run_test_case(f) => res,
+                        // Fail fast if a node exits outside of a requested stop or restart
+                        e = wait_for_unexpected_exit(&docker) => Err(e),
+                        e = wait_for_unexpected_process_exit(&supervisor) => Err(e),
+                    }
                  } => res,
                 _ = signal::ctrl_c() => {
                     println!("Initiating shutdown...");
@@ -110,6 +107,13 @@ impl<T: TestCase> TestCaseRunner<T> {
             }
         }
 
+        if let Err(_) | Ok(Err(_)) = result {
+            match f.export_docker_compose() {
+                Ok(path) => println!("Test environment exported to {}", path.display()),
+                Err(e) => eprintln!("Error exporting docker-compose file: {e}"),
+            }
+        }
+
         f.stop().await?;
 
         // Additional test cleanup
@@ -140,6 +144,16 @@ impl<T: TestCase> TestCaseRunner<T> {
         self.set_binary_path(CITREA_ENV, path)
     }
 
+    /// Sets the path for the previous Citrea release in the environment, see `TestFramework::previous_citrea_binary`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Location of the previous Citrea release binary.
+    ///
+    pub fn set_previous_citrea_path<P: AsRef<Path>>(self, path: P) -> Self {
+        self.set_binary_path(PREVIOUS_CITREA_ENV, path)
+    }
+
     /// Sets the path for the Citrea-cli binary in the environment.
     ///
     /// # Arguments
@@ -202,6 +216,12 @@ pub trait TestCase: Send + Sync + 'static {
         Some(1)
     }
 
+    /// Returns the pruning configuration of L2 node `kind`, pruning being disabled by default.
+    /// Override this method to enable pruning on the full node or provers.
+    fn pruning_config(_kind: NodeKind) -> Option<PruningConfig> {
+        None
+    }
+
     /// Returns the sequencer configuration for the test.
     /// Override this method to provide a custom sequencer configuration.
     fn sequencer_config() -> SequencerConfig {
@@ -220,6 +240,21 @@ pub trait TestCase: Send + Sync + 'static {
         LightClientProverConfig::default()
     }
 
+    /// Returns the full node configuration for the test.
+    /// citrea's full node config is empty, its RPC, storage and sync settings are part of
+    /// its rollup config, see `rollup_config(NodeKind::FullNode, ..)`.
+    fn full_node_config() -> EmptyConfig {
+        EmptyConfig
+    }
+
+    /// Returns the rollup configuration of L2 node `kind`.
+    /// `default` is generated by the framework, with ports, paths, keys and runner filled in.
+    /// Override this method to customize RPC limits, storage, DA monitoring or runner settings,
+    /// including the full node's, whose own config is empty.
+    fn rollup_config(_kind: NodeKind, default: RollupConfig) -> RollupConfig {
+        default
+    }
+
     /// Returns the test setup
     /// Override this method to add custom initialization logic
     async fn setup(&self, _framework: &mut TestFramework) -> Result<()> {
@@ -241,4 +276,181 @@ pub trait TestCase: Send + Sync + 'static {
     {
         Ok(())
     }
+
+    /// Definition the config hooks are resolved from, see `FileTestCase`
+    #[doc(hidden)]
+    fn definition(&self) -> Option<Arc<TestCaseDefinition>> {
+        None
+    }
+
+    /// Fails if the config hooks can't be resolved in the current context, see `FileTestCase`
+    #[doc(hidden)]
+    fn check_config_hooks() -> Result<()> {
+        Ok(())
+    }
+}
+
+tokio::task_local! {
+    // Definition of the test case being run, scoped by `TestCaseRunner::run`
+    static DEFINITION: Arc<TestCaseDefinition>;
+}
+
+/// Test case whose configuration is loaded from a `TestCaseDefinition` file.
+///
+/// Test logic is provided as a closure, i.e.
+///
+/// ```ignore
+/// FileTestCase::new("tests/definitions/basic.toml", |f| {
+///     Box::pin(async move {
+///         f.sequencer.as_ref().unwrap().client.send_publish_batch_request().await?;
+///         Ok(())
+///     })
+/// })?
+/// .run()
+/// .await
+/// ```
+///
+/// Config hooks resolve from the definition while the test case is run by a `TestCaseRunner`.
+/// `TestFramework::new` fails when called from outside of it, i.e. from a spawned task.
+pub struct FileTestCase<F> {
+    definition: Arc<TestCaseDefinition>,
+    run: F,
+}
+
+impl<F> FileTestCase<F>
+where
+    F: for<'a> FnMut(&'a mut TestFramework) -> BoxFuture<'a, Result<()>> + Send + Sync + 'static,
+{
+    /// Load and validate the definition at `path`.
+    pub fn new(path: impl AsRef<Path>, run: F) -> Result<Self> {
+        Self::from_definition(TestCaseDefinition::from_file(path)?, run)
+    }
+
+    pub fn from_definition(definition: TestCaseDefinition, run: F) -> Result<Self> {
+        // Fail early on invalid partial configs, they are expected to be valid once running
+        definition.test_config()?;
+        definition.sequencer_config()?;
+        definition.batch_prover_config()?;
+        definition.light_client_prover_config()?;
+        for kind in [
+            NodeKind::Sequencer,
+            NodeKind::BatchProver,
+            NodeKind::LightClientProver,
+            NodeKind::FullNode,
+        ] {
+            definition.rollup_config(kind, RollupConfig::default())?;
+        }
+
+        Ok(Self {
+            definition: Arc::new(definition),
+            run,
+        })
+    }
+
+    pub async fn run(self) -> Result<()> {
+        TestCaseRunner::new(self).run().await
+    }
+}
+
+fn try_definition() -> Result<Arc<TestCaseDefinition>> {
+    DEFINITION.try_with(Arc::clone).ok().context(
+        "FileTestCase configs are only available within TestCaseRunner::run, outside of spawned tasks",
+    )
+}
+
+fn definition() -> Arc<TestCaseDefinition> {
+    try_definition().expect("Checked by TestFramework::new")
+}
+
+#[async_trait]
+impl<F> TestCase for FileTestCase<F>
+where
+    F: for<'a> FnMut(&'a mut TestFramework) -> BoxFuture<'a, Result<()>> + Send + Sync + 'static,
+{
+    fn test_config() -> TestCaseConfig {
+        definition()
+            .test_config()
+            .expect("Test config validated on load")
+    }
+
+    fn test_env() -> TestCaseEnv {
+        definition().test_env()
+    }
+
+    fn bitcoin_config() -> BitcoinConfig {
+        definition().bitcoin_config()
+    }
+
+    fn scan_l1_start_height() -> Option<u64> {
+        definition().scan_l1_start_height()
+    }
+
+    fn pruning_config(kind: NodeKind) -> Option<PruningConfig> {
+        definition().pruning_config(kind)
+    }
+
+    fn sequencer_config() -> SequencerConfig {
+        definition()
+            .sequencer_config()
+            .expect("Sequencer config validated on load")
+    }
+
+    fn batch_prover_config() -> BatchProverConfig {
+        definition()
+            .batch_prover_config()
+            .expect("Batch prover config validated on load")
+    }
+
+    fn light_client_prover_config() -> LightClientProverConfig {
+        definition()
+            .light_client_prover_config()
+            .expect("Light client prover config validated on load")
+    }
+
+    fn rollup_config(kind: NodeKind, default: RollupConfig) -> RollupConfig {
+        definition()
+            .rollup_config(kind, default)
+            .expect("Rollup config validated on load")
+    }
+
+    async fn run_test(&mut self, framework: &mut TestFramework) -> Result<()> {
+        (self.run)(framework).await
+    }
+
+    fn definition(&self) -> Option<Arc<TestCaseDefinition>> {
+        Some(Arc::clone(&self.definition))
+    }
+
+    fn check_config_hooks() -> Result<()> {
+        try_definition().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Run = for<'a> fn(&'a mut TestFramework) -> BoxFuture<'a, Result<()>>;
+
+    #[tokio::test]
+    async fn test_file_test_case_scope() {
+        let definition = Arc::new(TestCaseDefinition::default());
+
+        assert!(FileTestCase::<Run>::check_config_hooks().is_err());
+        assert!(DEFINITION
+            .scope(definition.clone(), async {
+                FileTestCase::<Run>::check_config_hooks()
+            })
+            .await
+            .is_ok());
+        // Spawned tasks don't inherit the scope
+        assert!(DEFINITION
+            .scope(definition, async {
+                tokio::spawn(async { FileTestCase::<Run>::check_config_hooks() })
+                    .await
+                    .unwrap()
+            })
+            .await
+            .is_err());
+    }
 }
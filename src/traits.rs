@@ -1,17 +1,35 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    fmt,
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use async_trait::async_trait;
-use bollard::{container::StopContainerOptions, Docker};
+use bollard::{
+    query_parameters::{InspectContainerOptions, StopContainerOptions},
+    Docker,
+};
 use nix::{
     sys::signal::{self, Signal},
     unistd::Pid,
 };
 use tokio::process::Child;
-use tracing::info;
+use tracing::{info, warn};
 
 use super::Result;
-use crate::docker::{ContainerSpawnOutput, DockerEnv};
+use crate::{
+    docker::{ContainerSpawnOutput, DockerEnv},
+    Error,
+};
+
+/// Default grace period given to a node to exit on `stop`
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time given to a node to become ready
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub enum SpawnOutput {
@@ -19,6 +37,45 @@ pub enum SpawnOutput {
     Container(ContainerSpawnOutput),
 }
 
+/// Outcome of a node shutdown
+#[derive(Debug, Clone)]
+pub struct StopReport {
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// Whether the node was killed after its grace period
+    pub killed: bool,
+    pub duration: Duration,
+}
+
+impl StopReport {
+    /// Assert the node exited on its own within `limit`
+    pub fn assert_graceful(&self, limit: Duration) -> Result<()> {
+        if self.killed {
+            bail!("Node was killed, {self}");
+        }
+        if self.duration > limit {
+            bail!("Node took longer than {limit:?} to shut down, {self}");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StopReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "shut down in {:?}", self.duration)?;
+        if let Some(exit_code) = self.exit_code {
+            write!(f, " with exit code {exit_code}")?;
+        }
+        if let Some(signal) = self.signal {
+            write!(f, " by signal {signal}")?;
+        }
+        if self.killed {
+            write!(f, " after being killed")?;
+        }
+        Ok(())
+    }
+}
+
 /// The Node trait defines the common interface shared between
 /// BitcoinNode, BatchProver, LightClientProver, Sequencer and FullNode
 #[async_trait]
@@ -33,27 +90,15 @@ pub trait NodeT: Send {
     fn config_mut(&mut self) -> &mut Self::Config;
     fn config(&self) -> &Self::Config;
 
-    /// Stops the running node
-    async fn stop(&mut self) -> Result<()> {
-        match self.spawn_output() {
-            SpawnOutput::Child(process) => {
-                let pid = process.id().expect("Process missing id") as i32;
-                info!("Killing process {}", pid);
-                signal::kill(Pid::from_raw(pid), Signal::SIGTERM)
-                    .context("Failed to send SIGTERM signal to process")?;
-                Ok(())
-            }
-            SpawnOutput::Container(ContainerSpawnOutput { id, .. }) => {
-                info!("Stopping container {id}");
-                let docker =
-                    Docker::connect_with_local_defaults().context("Failed to connect to Docker")?;
-                docker
-                    .stop_container(id, Some(StopContainerOptions { t: 10 }))
-                    .await
-                    .context("Failed to stop Docker container")?;
-                Ok(())
-            }
-        }
+    /// Grace period given to the node to exit on `stop` before it gets killed
+    fn stop_timeout(&self) -> Duration {
+        DEFAULT_STOP_TIMEOUT
+    }
+
+    /// Stops the running node and waits for it to exit, killing it after `stop_timeout`
+    async fn stop(&mut self) -> Result<StopReport> {
+        let timeout = self.stop_timeout();
+        stop_spawn_output(self.spawn_output(), timeout).await
     }
 
     /// Wait for the node to be reachable by its client.
@@ -61,11 +106,80 @@ pub trait NodeT: Send {
 
     fn client(&self) -> &Self::Client;
 
-    fn env(&self) -> Vec<(&'static str, &'static str)> {
+    fn env(&self) -> Vec<(String, String)> {
         Vec::new()
     }
 }
 
+/// Send SIGTERM to a local process, or stop a container, and wait for it to exit.
+/// Escalates to SIGKILL after `timeout`.
+pub(crate) async fn stop_spawn_output(
+    spawn_output: &mut SpawnOutput,
+    timeout: Duration,
+) -> Result<StopReport> {
+    let start = Instant::now();
+    match spawn_output {
+        SpawnOutput::Child(process) => {
+            // Missing once the process was reaped, i.e. after a crash
+            if let Some(pid) = process.id() {
+                info!("Killing process {}", pid);
+                signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
+                    .context("Failed to send SIGTERM signal to process")?;
+            }
+
+            let (status, killed) = match tokio::time::timeout(timeout, process.wait()).await {
+                Ok(status) => (status?, false),
+                Err(_) => {
+                    warn!("Process didn't exit within {timeout:?}, sending SIGKILL");
+                    process.kill().await.context("Failed to kill process")?;
+                    (process.wait().await?, true)
+                }
+            };
+            Ok(StopReport {
+                exit_code: status.code(),
+                signal: status.signal(),
+                killed,
+                duration: start.elapsed(),
+            })
+        }
+        SpawnOutput::Container(ContainerSpawnOutput { id, .. }) => {
+            info!("Stopping container {id}");
+            let docker =
+                Docker::connect_with_local_defaults().context("Failed to connect to Docker")?;
+            // Docker escalates to SIGKILL after `t` seconds
+            docker
+                .stop_container(
+                    id,
+                    Some(StopContainerOptions {
+                        // Round up so that sub-second timeouts don't kill right away
+                        t: Some(timeout.as_secs_f64().ceil() as i32),
+                        ..Default::default()
+                    }),
+                )
+                .await
+                .map_err(Error::from)
+                .context("Failed to stop Docker container")?;
+            let duration = start.elapsed();
+
+            let exit_code = docker
+                .inspect_container(id, None::<InspectContainerOptions>)
+                .await
+                .map_err(Error::from)
+                .context("Failed to inspect Docker container")?
+                .state
+                .and_then(|state| state.exit_code)
+                .map(|exit_code| exit_code as i32);
+            Ok(StopReport {
+                exit_code,
+                signal: None,
+                // 128 + SIGKILL
+                killed: exit_code == Some(137),
+                duration,
+            })
+        }
+    }
+}
+
 // Two patterns supported :
 // - Call wait_until_stopped, runs any extra commands needed for testing purposes, call start again.
 // - Call restart if you need to wait for node to be fully shutdown and brough back up with new config.
@@ -87,4 +201,10 @@ pub trait Restart: NodeT + Send {
         self.wait_until_stopped().await?;
         self.start(new_config, extra_args).await
     }
+
+    /// Restart the node on its existing storage with another citrea `binary`.
+    /// Checks that a release, newer or older, can take over a datadir written by another one.
+    async fn upgrade(&mut self, _binary: PathBuf) -> Result<()> {
+        bail!("Only citrea nodes can be upgraded")
+    }
 }
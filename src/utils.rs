@@ -85,6 +85,15 @@ pub fn copy_directory(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Resul
 
 pub fn tail_file(path: &Path, lines: usize) -> Result<()> {
     println!("tailing path : {path:?}");
+    for line in tail_lines(path, lines)? {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+/// Returns the last `lines` lines of the file at `path`
+pub fn tail_lines(path: &Path, lines: usize) -> Result<Vec<String>> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
     let mut last_lines = Vec::new();
@@ -98,9 +107,5 @@ pub fn tail_file(path: &Path, lines: usize) -> Result<()> {
         last_lines.push(line);
     }
 
-    for line in last_lines {
-        println!("{line}");
-    }
-
-    Ok(())
+    Ok(last_lines)
 }
@@ -0,0 +1,238 @@
+//! Polling of a condition until it holds or times out.
+
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::time::{sleep, Instant};
+use tracing::{info, trace};
+
+use crate::{node::NodeKind, Error, Result};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+type Retryable = Arc<dyn Fn(&anyhow::Error) -> bool + Send + Sync>;
+
+/// Polls a condition on an interval until it holds, failing after a timeout.
+///
+/// Poll errors are returned right away unless classified as retryable, see `with_retryable`.
+/// Fails with `Error::Timeout`, carrying the last observed value or the last retryable error.
+///
+/// ```ignore
+/// Wait::new("L2 block 10")
+///     .with_timeout(Duration::from_secs(60))
+///     .until(|| client.ledger_get_head_l2_block_height(), |height| *height >= 10)
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct Wait {
+    node: Option<NodeKind>,
+    description: String,
+    timeout: Duration,
+    interval: Duration,
+    backoff: f64,
+    max_interval: Duration,
+    progress: Option<Duration>,
+    retryable: Retryable,
+}
+
+impl Wait {
+    /// Wait for `description`, i.e. "L2 block 10", polling every second for up to 30 seconds
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            node: None,
+            description: description.into(),
+            timeout: DEFAULT_TIMEOUT,
+            interval: DEFAULT_INTERVAL,
+            backoff: 1.0,
+            max_interval: DEFAULT_INTERVAL,
+            progress: None,
+            retryable: Arc::new(|_| false),
+        }
+    }
+
+    /// Node the wait is about, reported in the timeout error
+    pub fn with_node(mut self, node: NodeKind) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time between two polls, before backoff
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self.max_interval = self.max_interval.max(interval);
+        self
+    }
+
+    /// Multiply the interval by `factor` after each poll, up to `max_interval`.
+    /// Panics unless `factor` is finite and at least 1.
+    pub fn with_backoff(mut self, factor: f64, max_interval: Duration) -> Self {
+        assert!(
+            factor.is_finite() && factor >= 1.0,
+            "Backoff factor has to be finite and >= 1, got {factor}"
+        );
+        self.backoff = factor;
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Log what is being waited for every `interval`
+    pub fn with_progress(mut self, interval: Duration) -> Self {
+        self.progress = Some(interval);
+        self
+    }
+
+    /// Keep polling on errors `retryable` returns true for, instead of failing
+    pub fn with_retryable(
+        mut self,
+        retryable: impl Fn(&anyhow::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retryable = Arc::new(retryable);
+        self
+    }
+
+    /// Keep polling on any error
+    pub fn retry_all_errors(self) -> Self {
+        self.with_retryable(|_| true)
+    }
+
+    /// Poll `f` until `done` holds for its output, and return that output
+    pub async fn until<T, F, Fut>(&self, mut f: F, done: impl Fn(&T) -> bool) -> Result<T>
+    where
+        T: Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let start = Instant::now();
+        let mut last_progress = start;
+        let mut interval = self.interval;
+
+        loop {
+            let (last_observed, last_error) = match f().await {
+                Ok(value) if done(&value) => return Ok(value),
+                Ok(value) => (Some(format!("{value:?}")), None),
+                Err(e) if (self.retryable)(&e) => (None, Some(format!("{e:#}"))),
+                Err(e) => return Err(e),
+            };
+
+            let elapsed = start.elapsed();
+            if elapsed >= self.timeout {
+                return Err(Error::Timeout {
+                    node: self.node,
+                    description: self.description.clone(),
+                    timeout: self.timeout,
+                    last_observed,
+                    last_error,
+                }
+                .into());
+            }
+
+            let observed = match (&last_observed, &last_error) {
+                (_, Some(e)) => format!("last error: {e}"),
+                (Some(value), None) => format!("last observed {value}"),
+                (None, None) => String::new(),
+            };
+            trace!("Waiting for {}, {observed}", self.description());
+            if let Some(progress) = self.progress {
+                if last_progress.elapsed() >= progress {
+                    info!(
+                        "Still waiting for {} after {elapsed:?}, {observed}",
+                        self.description()
+                    );
+                    last_progress = Instant::now();
+                }
+            }
+
+            sleep(interval.min(self.timeout - elapsed)).await;
+            interval = interval.mul_f64(self.backoff).min(self.max_interval);
+        }
+    }
+
+    // Description prefixed with the node, if any
+    fn description(&self) -> String {
+        match self.node {
+            Some(node) => format!("{node} {}", self.description),
+            None => self.description.clone(),
+        }
+    }
+
+    /// Poll `f` until it returns true
+    pub async fn until_true<F, Fut>(&self, f: F) -> Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<bool>>,
+    {
+        self.until(f, |done| *done).await.map(drop)
+    }
+}
+
+impl Debug for Wait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wait")
+            .field("node", &self.node)
+            .field("description", &self.description)
+            .field("timeout", &self.timeout)
+            .field("interval", &self.interval)
+            .field("backoff", &self.backoff)
+            .field("max_interval", &self.max_interval)
+            .field("progress", &self.progress)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let polls = AtomicU64::new(0);
+        let poll = || async { Ok(polls.fetch_add(1, Ordering::SeqCst)) };
+        let wait = Wait::new("counter").with_interval(Duration::from_millis(1));
+
+        assert_eq!(wait.until(poll, |n| *n >= 3).await.unwrap(), 3);
+
+        let e = wait
+            .clone()
+            .with_timeout(Duration::from_millis(20))
+            .until(poll, |n| *n >= 1000)
+            .await
+            .unwrap_err();
+        assert!(e.downcast_ref::<Error>().is_some_and(Error::is_timeout));
+        assert!(e
+            .to_string()
+            .starts_with("Timeout after 20ms waiting for counter, last observed"));
+
+        let failing = || async { Err::<u64, _>(anyhow!("node not connected")) };
+        assert_eq!(
+            wait.until(failing, |_| true).await.unwrap_err().to_string(),
+            "node not connected"
+        );
+        let e = wait
+            .with_timeout(Duration::from_millis(20))
+            .retry_all_errors()
+            .until(failing, |_| true)
+            .await
+            .unwrap_err();
+        assert!(e.to_string().ends_with("last error: node not connected"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Backoff factor has to be finite and >= 1")]
+    fn test_invalid_backoff() {
+        Wait::new("counter").with_backoff(0.5, Duration::from_secs(1));
+    }
+}
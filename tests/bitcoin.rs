@@ -23,6 +23,7 @@ impl TestCase for BasicSyncTest {
             docker: TestCaseDockerConfig {
                 bitcoin: true,
                 citrea: true,
+                ..Default::default()
             },
             ..Default::default()
         }
@@ -82,6 +83,7 @@ impl TestCase for RestartBitcoinTest {
             docker: TestCaseDockerConfig {
                 bitcoin: true,
                 citrea: true,
+                ..Default::default()
             },
             ..Default::default()
         }
@@ -89,7 +91,7 @@ impl TestCase for RestartBitcoinTest {
 
     fn bitcoin_config() -> BitcoinConfig {
         BitcoinConfig {
-            extra_args: vec!["-txindex=0"],
+            extra_args: vec!["-txindex=0".to_string()],
             ..Default::default()
         }
     }
@@ -98,7 +100,7 @@ impl TestCase for RestartBitcoinTest {
         let da = f.bitcoin_nodes.get_mut(0).unwrap();
         // Add txindex flag to check that restart takes into account the extra args
         let new_conf = BitcoinConfig {
-            extra_args: vec!["-txindex=1"],
+            extra_args: vec!["-txindex=1".to_string()],
             ..da.config.clone()
         };
 
@@ -19,6 +19,7 @@ impl TestCase for DockerIntegrationTest {
             docker: TestCaseDockerConfig {
                 bitcoin: true,
                 citrea: true,
+                ..Default::default()
             },
             ..Default::default()
         }
@@ -65,3 +66,91 @@ impl TestCase for DockerIntegrationTest {
 async fn test_docker_integration() -> Result<()> {
     TestCaseRunner::new(DockerIntegrationTest).run().await
 }
+
+struct DockerCrashRestartTest;
+
+#[async_trait]
+impl TestCase for DockerCrashRestartTest {
+    fn test_config() -> TestCaseConfig {
+        TestCaseConfig {
+            with_full_node: true,
+            docker: TestCaseDockerConfig {
+                bitcoin: true,
+                citrea: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    async fn run_test(&mut self, f: &mut TestFramework) -> Result<()> {
+        let sequencer = f.sequencer.as_ref().unwrap();
+        for _ in 0..3 {
+            sequencer.client.send_publish_batch_request().await?;
+        }
+
+        let full_node = f.full_node.as_mut().unwrap();
+        full_node.wait_for_l2_height(3, None).await?;
+        full_node.crash_restart(None).await?;
+
+        // The recreated container runs on the same volume
+        assert!(full_node.client.ledger_get_head_l2_block_height().await? >= 3);
+
+        f.sequencer
+            .as_ref()
+            .unwrap()
+            .client
+            .send_publish_batch_request()
+            .await?;
+        f.full_node
+            .as_ref()
+            .unwrap()
+            .wait_for_l2_height(4, None)
+            .await
+    }
+}
+
+#[tokio::test]
+async fn test_docker_crash_restart() -> Result<()> {
+    TestCaseRunner::new(DockerCrashRestartTest).run().await
+}
+
+struct DockerSpawnNodeTest;
+
+#[async_trait]
+impl TestCase for DockerSpawnNodeTest {
+    fn test_config() -> TestCaseConfig {
+        TestCaseConfig {
+            with_full_node: true,
+            docker: TestCaseDockerConfig {
+                bitcoin: true,
+                citrea: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    async fn run_test(&mut self, f: &mut TestFramework) -> Result<()> {
+        let sequencer = f.sequencer.as_ref().unwrap();
+        for _ in 0..3 {
+            sequencer.client.send_publish_batch_request().await?;
+        }
+
+        // Runs next to the test full node, in its own container and volume
+        let config = f.new_full_node_config()?;
+        let spawned = f.spawn_full_node(config).await?;
+        spawned.wait_for_l2_height(3, None).await?;
+
+        f.full_node
+            .as_ref()
+            .unwrap()
+            .wait_for_l2_height(3, None)
+            .await
+    }
+}
+
+#[tokio::test]
+async fn test_docker_spawn_node() -> Result<()> {
+    TestCaseRunner::new(DockerSpawnNodeTest).run().await
+}